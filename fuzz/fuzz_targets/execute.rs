@@ -0,0 +1,46 @@
+// Runs arbitrary ROMs under every quirk preset. The first byte of the input picks the preset and
+// how often keys change, and the rest is loaded as the ROM.
+//
+// To run it, install cargo-fuzz and use a nightly toolchain:
+//     cargo +nightly fuzz run execute
+
+#![no_main]
+use chip8::quirks::{Quirks, QUIRK_PRESETS};
+use chip8::Chip8;
+use libfuzzer_sys::fuzz_target;
+
+const MAX_STEPS: usize = 10_000;
+const CYCLES_PER_FRAME: usize = 8;
+
+fuzz_target!(|data: &[u8]| {
+    let (settings, rom) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut chip8 = Chip8::power_up();
+    chip8.seed_rng(0);
+    if chip8.try_load_rom(rom).is_err() {
+        return;
+    }
+    //loading a ROM the database knows about replaces the quirks, so the preset is set afterwards
+    chip8.set_quirks(Quirks::new(QUIRK_PRESETS[(*settings as usize) % QUIRK_PRESETS.len()]));
+
+    let key_interval = ((*settings >> 2) as usize) + 1;
+    let mut key = 0;
+    for step in 0..MAX_STEPS {
+        //only errors are allowed, so any panic is a bug
+        if chip8.step().is_err() {
+            return;
+        }
+        if step % CYCLES_PER_FRAME == CYCLES_PER_FRAME - 1 {
+            chip8.decrement_timers();
+        }
+        if step % key_interval == 0 {
+            key = rom.get(step % rom.len().max(1)).copied().unwrap_or(0) & 0x0F;
+            chip8.press_key(key);
+        } else if step % key_interval == key_interval / 2 {
+            chip8.release_key(key);
+        }
+    }
+});
@@ -0,0 +1,404 @@
+// Static analysis of a ROM. Rather than disassembling every word in order, which turns sprite data
+// into nonsense instructions, this follows jumps, calls and skips from the entry point to find the
+// bytes that can actually be executed. Those instructions are split into basic blocks, which are
+// grouped into the subroutines that CALL instructions lead to.
+//
+// BNNN jumps to an address that depends on a register, so it can't be followed statically. The
+// analysis records where those jumps are, and anything only reachable through them shows up as data.
+
+use super::disassembler::Instruction;
+use super::memory::{Memory, ADDRESS_MASK, MEMORY_SIZE};
+use super::symbols::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    //the next instruction in memory
+    FallThrough,
+    Jump,
+    //the instruction after a skip, taken when the condition is true
+    Skip,
+    Call,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: u16,
+    //the address of the last instruction in the block
+    pub end: u16,
+    pub successors: Vec<(u16, EdgeKind)>,
+}
+
+pub struct Analysis {
+    entry: u16,
+    instruction_starts: BTreeSet<u16>,
+    blocks: BTreeMap<u16, BasicBlock>,
+    subroutines: BTreeSet<u16>,
+    indirect_jumps: BTreeSet<u16>,
+}
+
+impl Analysis {
+    pub fn new(memory: &Memory, entry: u16) -> Self {
+        let mut instruction_starts = BTreeSet::new();
+        let mut leaders = BTreeSet::new();
+        let mut subroutines = BTreeSet::new();
+        let mut indirect_jumps = BTreeSet::new();
+        let mut pending = vec![entry];
+        leaders.insert(entry);
+
+        while let Some(address) = pending.pop() {
+            if !instruction_starts.insert(address) {
+                continue;
+            }
+            let instruction = Instruction::decode(read_opcode(memory, address));
+            if let Instruction::Call(target) = instruction {
+                subroutines.insert(target);
+            }
+            if let Instruction::JpV0(_) = instruction {
+                indirect_jumps.insert(address);
+            }
+            let successors = get_successors(address, instruction);
+            if ends_block(instruction) {
+                leaders.extend(successors.iter().map(|(target, _)| *target));
+            }
+            pending.extend(successors.iter().map(|(target, _)| *target));
+        }
+
+        let mut blocks = BTreeMap::new();
+        for leader in leaders.iter() {
+            let mut end = *leader;
+            loop {
+                let instruction = Instruction::decode(read_opcode(memory, end));
+                let next = next_address(end, 2);
+                if ends_block(instruction) || leaders.contains(&next) || !instruction_starts.contains(&next) {
+                    let mut successors = get_successors(end, instruction);
+                    if !ends_block(instruction) && instruction_starts.contains(&next) {
+                        successors.push((next, EdgeKind::FallThrough));
+                    }
+                    blocks.insert(*leader, BasicBlock { start: *leader, end, successors });
+                    break;
+                }
+                end = next;
+            }
+        }
+
+        Analysis {
+            entry,
+            instruction_starts,
+            blocks,
+            subroutines,
+            indirect_jumps,
+        }
+    }
+
+    pub fn get_entry(&self) -> u16 {
+        self.entry
+    }
+
+    // Whether an instruction that can be reached from the entry point starts at this address
+    pub fn is_instruction_start(&self, address: u16) -> bool {
+        self.instruction_starts.contains(&address)
+    }
+
+    // Whether this byte is part of a reachable instruction
+    pub fn is_code(&self, address: u16) -> bool {
+        self.is_instruction_start(address) || self.is_instruction_start(next_address(address, MEMORY_SIZE - 1))
+    }
+
+    pub fn get_blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn get_block(&self, start: u16) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    // The entry points of every subroutine that is called
+    pub fn get_subroutines(&self) -> &BTreeSet<u16> {
+        &self.subroutines
+    }
+
+    // The addresses of the BNNN instructions, whose targets aren't known until the ROM runs
+    pub fn get_indirect_jumps(&self) -> &BTreeSet<u16> {
+        &self.indirect_jumps
+    }
+
+    // Groups the blocks by the subroutine they belong to, following every edge except calls. The
+    // entry point's group comes first. A block shared by two subroutines goes in the first one.
+    pub fn get_functions(&self) -> Vec<(u16, Vec<u16>)> {
+        let mut assigned = BTreeSet::new();
+        let mut functions = Vec::new();
+        let entries = std::iter::once(self.entry).chain(self.subroutines.iter().copied().filter(|address| *address != self.entry));
+        for function in entries {
+            let mut members = Vec::new();
+            let mut pending = vec![function];
+            while let Some(start) = pending.pop() {
+                let block = match self.blocks.get(&start) {
+                    Some(block) => block,
+                    None => continue,
+                };
+                if !assigned.insert(start) {
+                    continue;
+                }
+                members.push(start);
+                for (target, kind) in block.successors.iter() {
+                    if *kind != EdgeKind::Call {
+                        pending.push(*target);
+                    }
+                }
+            }
+            members.sort_unstable();
+            functions.push((function, members));
+        }
+        functions
+    }
+
+    // Lists memory from start to end, disassembling the reachable instructions and showing
+    // everything else as rows of data bytes. Labels, comments and data regions come from symbols.
+    pub fn get_listing(&self, memory: &Memory, start: usize, end: usize, symbols: &SymbolTable) -> String {
+        let end = end.min(MEMORY_SIZE);
+        let is_instruction = |address: usize| self.is_instruction_start(address as u16) && !symbols.is_data(address as u16);
+
+        let mut listing = String::new();
+        let mut address = start;
+        while address < end {
+            if let Some(name) = self.get_name(address as u16, symbols) {
+                listing.push_str(&format!("\n{}:\n", name));
+            }
+            let mut notes = Vec::new();
+            if self.indirect_jumps.contains(&(address as u16)) && is_instruction(address) {
+                notes.push("indirect jump");
+            }
+            if let Some(comment) = symbols.get_comment(address as u16) {
+                notes.push(comment);
+            }
+            let note = if notes.is_empty() { String::new() } else { format!("  ; {}", notes.join(", ")) };
+
+            if is_instruction(address) {
+                let opcode = read_opcode(memory, address as u16);
+                listing.push_str(&format!(
+                    "{:04X}  {:04X}  {}{}\n",
+                    address,
+                    opcode,
+                    symbols.format_instruction(Instruction::decode(opcode)),
+                    note
+                ));
+                address += 2;
+            } else {
+                //data lines stop at labels, so that every label starts a line of its own
+                let length = 1
+                    + (address + 1..end)
+                        .take(DATA_BYTES_PER_LINE - 1)
+                        .take_while(|data| !is_instruction(*data) && self.get_name(*data as u16, symbols).is_none())
+                        .count();
+                let bytes: Vec<String> = memory.memory[address..address + length].iter().map(|byte| format!("{:#04X}", byte)).collect();
+                listing.push_str(&format!("{:04X}        DB {}{}\n", address, bytes.join(", "), note));
+                address += length;
+            }
+        }
+        listing
+    }
+
+    // Exports the control flow graph in Graphviz's DOT format, with a cluster per subroutine
+    pub fn get_dot(&self, memory: &Memory, symbols: &SymbolTable) -> String {
+        let mut dot = String::from("digraph chip8 {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (function, members) in self.get_functions() {
+            let name = self.get_name(function, symbols).unwrap_or_else(|| String::from("main"));
+            dot.push_str(&format!("    subgraph cluster_{:03X} {{\n        label=\"{}\";\n", function, name));
+            for start in members {
+                let block = &self.blocks[&start];
+                let mut label = String::new();
+                let mut address = block.start;
+                loop {
+                    let opcode = read_opcode(memory, address);
+                    label.push_str(&format!("{:04X}  {}\\l", address, symbols.format_instruction(Instruction::decode(opcode))));
+                    if address == block.end {
+                        break;
+                    }
+                    address = next_address(address, 2);
+                }
+                let colour = if self.indirect_jumps.contains(&block.end) { ", color=red" } else { "" };
+                dot.push_str(&format!("        b{:03X} [label=\"{}\"{}];\n", block.start, label, colour));
+            }
+            dot.push_str("    }\n");
+        }
+        for block in self.blocks.values() {
+            for (target, kind) in block.successors.iter() {
+                let style = match kind {
+                    EdgeKind::FallThrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Call => " [label=\"call\", style=dashed]",
+                };
+                dot.push_str(&format!("    b{:03X} -> b{:03X}{};\n", block.start, target, style));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    //the label from the symbol table, or a generated one for subroutines that don't have one
+    fn get_name(&self, address: u16, symbols: &SymbolTable) -> Option<String> {
+        match symbols.get_label(address) {
+            Some(label) => Some(String::from(label)),
+            None if self.subroutines.contains(&address) => Some(format!("sub_{:03X}", address)),
+            None => None,
+        }
+    }
+}
+
+const DATA_BYTES_PER_LINE: usize = 8;
+
+fn read_opcode(memory: &Memory, address: u16) -> u16 {
+    let address = address as usize;
+    ((memory.memory[address & ADDRESS_MASK] as u16) << 8) | memory.memory[(address + 1) & ADDRESS_MASK] as u16
+}
+
+fn next_address(address: u16, offset: usize) -> u16 {
+    ((address as usize + offset) & ADDRESS_MASK) as u16
+}
+
+//whether the instruction can go anywhere other than the next instruction
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Ret
+            | Instruction::Jp(_)
+            | Instruction::JpV0(_)
+            | Instruction::Call(_)
+            | Instruction::Invalid(_)
+            | Instruction::SeByte(..)
+            | Instruction::SneByte(..)
+            | Instruction::SeRegister(..)
+            | Instruction::SneRegister(..)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_)
+    )
+}
+
+fn get_successors(address: u16, instruction: Instruction) -> Vec<(u16, EdgeKind)> {
+    match instruction {
+        Instruction::Ret | Instruction::JpV0(_) | Instruction::Invalid(_) => Vec::new(),
+        Instruction::Jp(target) => vec![(target, EdgeKind::Jump)],
+        //CALL pushes its own address and RET returns to the instruction after it
+        Instruction::Call(target) => vec![(target, EdgeKind::Call), (next_address(address, 2), EdgeKind::FallThrough)],
+        Instruction::SeByte(..) | Instruction::SneByte(..) | Instruction::SeRegister(..) | Instruction::SneRegister(..) | Instruction::Skp(_) | Instruction::Sknp(_) => {
+            vec![(next_address(address, 2), EdgeKind::FallThrough), (next_address(address, 4), EdgeKind::Skip)]
+        }
+        _ => vec![(next_address(address, 2), EdgeKind::FallThrough)],
+    }
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use super::*;
+
+    fn analyse(program: &[u8]) -> (Memory, Analysis) {
+        let mut memory = Memory::new();
+        memory.load_program(program).unwrap();
+        let analysis = Analysis::new(&memory, 0x200);
+        (memory, analysis)
+    }
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 20] = [
+        0xA2, 0x10, //0x200 LD I, 0x210
+        0x22, 0x0C, //0x202 CALL 0x20C
+        0x30, 0x01, //0x204 SE V0, 0x01
+        0x12, 0x04, //0x206 JP 0x204
+        0xB2, 0x00, //0x208 JP V0, 0x200
+        0xFF, 0xFF, //0x20A data
+        0xD0, 0x15, //0x20C DRW V0, V1, 5
+        0x00, 0xEE, //0x20E RET
+        0xF0, 0x90, //0x210 sprite data
+        0x90, 0xF0, //0x212 sprite data
+    ];
+
+    #[test]
+    fn analysis_separates_code_and_data() {
+        let (_, analysis) = analyse(&PROGRAM);
+        for address in [0x200, 0x202, 0x204, 0x206, 0x208, 0x20C, 0x20E].iter() {
+            assert!(analysis.is_instruction_start(*address), "{:03X}", address);
+        }
+        assert!(analysis.is_code(0x201));
+        for address in [0x20A, 0x20B, 0x210, 0x212].iter() {
+            assert!(!analysis.is_code(*address), "{:03X}", address);
+        }
+        assert_eq!(analysis.get_subroutines().iter().copied().collect::<Vec<u16>>(), vec![0x20C]);
+        assert_eq!(analysis.get_indirect_jumps().iter().copied().collect::<Vec<u16>>(), vec![0x208]);
+    }
+
+    #[test]
+    fn analysis_basic_blocks() {
+        let (_, analysis) = analyse(&PROGRAM);
+        let starts: Vec<u16> = analysis.get_blocks().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0x200, 0x204, 0x206, 0x208, 0x20C]);
+
+        let first = analysis.get_block(0x200).unwrap();
+        assert_eq!(first.end, 0x202);
+        assert_eq!(first.successors, vec![(0x20C, EdgeKind::Call), (0x204, EdgeKind::FallThrough)]);
+        assert_eq!(analysis.get_block(0x204).unwrap().successors, vec![(0x206, EdgeKind::FallThrough), (0x208, EdgeKind::Skip)]);
+        assert_eq!(analysis.get_block(0x20C).unwrap().end, 0x20E);
+        assert!(analysis.get_block(0x208).unwrap().successors.is_empty());
+
+        assert_eq!(analysis.get_functions(), vec![(0x200, vec![0x200, 0x204, 0x206, 0x208]), (0x20C, vec![0x20C])]);
+    }
+
+    #[test]
+    fn analysis_output() {
+        let (memory, analysis) = analyse(&PROGRAM);
+        let listing = analysis.get_listing(&memory, 0x200, 0x200 + PROGRAM.len(), &SymbolTable::new());
+        let expected = "0200  A210  LD I, 0x210
+0202  220C  CALL 0x20C
+0204  3001  SE V0, 0x01
+0206  1204  JP 0x204
+0208  B200  JP V0, 0x200  ; indirect jump
+020A        DB 0xFF, 0xFF
+
+sub_20C:
+020C  D015  DRW V0, V1, 5
+020E  00EE  RET
+0210        DB 0xF0, 0x90, 0x90, 0xF0
+";
+        assert_eq!(listing, expected);
+
+        let dot = analysis.get_dot(&memory, &SymbolTable::new());
+        assert!(dot.contains("subgraph cluster_20C {\n        label=\"sub_20C\";\n        b20C [label=\"020C  DRW V0, V1, 5\\l020E  RET\\l\"];"));
+        assert!(dot.contains("b200 -> b20C [label=\"call\", style=dashed];"));
+        assert!(dot.contains("b208 [label=\"0208  JP V0, 0x200\\l\", color=red];"));
+    }
+
+    #[test]
+    fn analysis_output_with_symbols() {
+        let (memory, analysis) = analyse(&PROGRAM);
+        let symbols = SymbolTable::parse(
+            ":label main 0x200
+:label draw 0x20C
+:comment 0x20C draws a square
+:data 0x210 0x213 square
+:data 0x208 0x209",
+        )
+        .unwrap();
+        let listing = analysis.get_listing(&memory, 0x200, 0x200 + PROGRAM.len(), &symbols);
+        let expected = "
+main:
+0200  A210  LD I, square
+0202  220C  CALL draw
+0204  3001  SE V0, 0x01
+0206  1204  JP 0x204
+0208        DB 0xB2, 0x00, 0xFF, 0xFF
+
+draw:
+020C  D015  DRW V0, V1, 5  ; draws a square
+020E  00EE  RET
+
+square:
+0210        DB 0xF0, 0x90, 0x90, 0xF0
+";
+        assert_eq!(listing, expected);
+
+        let dot = analysis.get_dot(&memory, &symbols);
+        assert!(dot.contains("label=\"draw\";"));
+        assert!(dot.contains("0200  LD I, square\\l0202  CALL draw\\l"));
+    }
+}
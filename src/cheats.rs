@@ -0,0 +1,340 @@
+// Finds where a ROM keeps a value, like the number of lives left, and pins it so it never changes.
+//
+// A search starts with a snapshot of memory and the V registers, in which every byte is a
+// candidate. Each later snapshot is compared with the one before it, keeping only the candidates
+// that changed in the expected way: lose a life, keep the ones that decreased, and so on until only
+// a few are left. Those can then be pinned to a value that is written back every frame.
+//
+// Cheats are saved in a line based format tied to the ROM they were found in:
+//     # chip8 cheats 1
+//     rom 0b6c1ef2c7b2b7a1f6d6a07e1c5f5e8ea7d1c4f3
+//     V3 05 lives
+//     0x2F0 09
+// which is the location, the value in hex, and an optional name.
+
+use super::cpu::{CPU, REGISTER_COUNT};
+use super::memory::{Memory, MEMORY_SIZE};
+use std::error;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+pub const HEADER: &str = "# chip8 cheats 1";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Location {
+    Memory(u16),
+    V(usize),
+}
+
+impl Location {
+    // Accepts V0 to VF, or a hex address with an optional 0x prefix
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(register) = text.strip_prefix('V').or_else(|| text.strip_prefix('v')) {
+            return match usize::from_str_radix(register, 16) {
+                Ok(x) if register.len() == 1 && x < REGISTER_COUNT => Some(Location::V(x)),
+                _ => None,
+            };
+        }
+        let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+        match usize::from_str_radix(digits, 16) {
+            Ok(address) if address < MEMORY_SIZE => Some(Location::Memory(address as u16)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Location::Memory(address) => write!(f, "{:#05X}", address),
+            Location::V(x) => write!(f, "V{:X}", x),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Snapshot {
+    memory: Vec<u8>,
+    v: [u8; REGISTER_COUNT],
+}
+
+impl Snapshot {
+    pub fn new(memory: &Memory, cpu: &CPU) -> Self {
+        let mut v = [0; REGISTER_COUNT];
+        v.copy_from_slice(cpu.get_v_registers());
+        Snapshot {
+            memory: memory.memory.to_vec(),
+            v,
+        }
+    }
+
+    pub fn get(&self, location: Location) -> u8 {
+        match location {
+            Location::Memory(address) => self.memory[address as usize],
+            Location::V(x) => self.v[x],
+        }
+    }
+}
+
+// How a candidate's value has to have changed since the last snapshot for it to be kept
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    Equal = 0,
+    Changed = 1,
+    Increased = 2,
+    Decreased = 3,
+}
+
+impl SearchFilter {
+    pub fn matches(self, before: u8, after: u8) -> bool {
+        match self {
+            SearchFilter::Equal => after == before,
+            SearchFilter::Changed => after != before,
+            SearchFilter::Increased => after > before,
+            SearchFilter::Decreased => after < before,
+        }
+    }
+}
+
+pub struct MemorySearch {
+    snapshot: Snapshot,
+    candidates: Vec<Location>,
+}
+
+impl MemorySearch {
+    // Starts a search in which every byte of memory and every V register is a candidate
+    pub fn new(snapshot: Snapshot) -> Self {
+        let memory = (0..MEMORY_SIZE).map(|address| Location::Memory(address as u16));
+        let registers = (0..REGISTER_COUNT).map(Location::V);
+        MemorySearch {
+            snapshot,
+            candidates: registers.chain(memory).collect(),
+        }
+    }
+
+    // Keeps the candidates whose value changed in the given way since the last snapshot
+    pub fn filter(&mut self, snapshot: Snapshot, filter: SearchFilter) {
+        let before = &self.snapshot;
+        self.candidates.retain(|location| filter.matches(before.get(*location), snapshot.get(*location)));
+        self.snapshot = snapshot;
+    }
+
+    // Keeps the candidates that hold value now, for when the value on screen is known
+    pub fn filter_value(&mut self, snapshot: Snapshot, value: u8) {
+        self.candidates.retain(|location| snapshot.get(*location) == value);
+        self.snapshot = snapshot;
+    }
+
+    pub fn get_candidates(&self) -> &[Location] {
+        &self.candidates
+    }
+
+    // The value of a location in the most recent snapshot
+    pub fn get_value(&self, location: Location) -> u8 {
+        self.snapshot.get(location)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub location: Location,
+    pub value: u8,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatList {
+    //the SHA-1 of the ROM the cheats were found in
+    rom_hash: String,
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn new(rom_hash: &str) -> Self {
+        CheatList {
+            rom_hash: String::from(rom_hash),
+            cheats: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CheatError> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        match lines.next() {
+            Some((_, line)) if line.trim() == HEADER => {}
+            _ => return Err(CheatError::MissingHeader),
+        }
+
+        let mut cheats = CheatList::default();
+        for (index, line) in lines {
+            let invalid = CheatError::InvalidLine(index + 1);
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("rom") => cheats.rom_hash = String::from(fields.next().ok_or(invalid)?),
+                Some(location) => {
+                    let location = Location::parse(location).ok_or(invalid)?;
+                    let value = fields.next().and_then(|value| u8::from_str_radix(value, 16).ok()).ok_or(invalid)?;
+                    let name = fields.collect::<Vec<&str>>().join(" ");
+                    cheats.pin(location, value, &name);
+                }
+                None => {}
+            }
+        }
+        Ok(cheats)
+    }
+
+    pub fn get_rom_hash(&self) -> &str {
+        &self.rom_hash
+    }
+
+    // Pins a location to a value, replacing any cheat that already pinned it
+    pub fn pin(&mut self, location: Location, value: u8, name: &str) {
+        self.unpin(location);
+        self.cheats.push(Cheat {
+            location,
+            value,
+            name: String::from(name),
+        });
+    }
+
+    // Returns false if the location wasn't pinned
+    pub fn unpin(&mut self, location: Location) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.location != location);
+        self.cheats.len() != count
+    }
+
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Should be called once a frame, so the ROM never sees a pinned value change for long
+    pub fn apply(&self, memory: &mut Memory, cpu: &mut CPU) {
+        for cheat in self.cheats.iter() {
+            match cheat.location {
+                Location::Memory(address) => memory.memory[address as usize] = cheat.value,
+                Location::V(x) => cpu.set_v_register(x, cheat.value),
+            }
+        }
+    }
+}
+
+// Writes the cheats out in the format parse reads
+impl fmt::Display for CheatList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {}", self.rom_hash)?;
+        for cheat in self.cheats.iter() {
+            if cheat.name.is_empty() {
+                writeln!(f, "{} {:02X}", cheat.location, cheat.value)?;
+            } else {
+                writeln!(f, "{} {:02X} {}", cheat.location, cheat.value, cheat.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatError {
+    MissingHeader,
+    InvalidLine(usize),
+    //the cheats were found in a different ROM to the one that is loaded
+    DifferentRom,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheatError::MissingHeader => write!(f, "Not a cheat file, the first line should be \"{}\"", HEADER),
+            CheatError::InvalidLine(line) => write!(f, "Invalid cheat on line {}", line),
+            CheatError::DifferentRom => write!(f, "The cheats are for a different ROM"),
+        }
+    }
+}
+
+impl error::Error for CheatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod cheats_tests {
+    use super::*;
+
+    #[test]
+    fn cheats_location_parse() {
+        assert_eq!(Location::parse("V3"), Some(Location::V(3)));
+        assert_eq!(Location::parse("vf"), Some(Location::V(15)));
+        assert_eq!(Location::parse("0x2F0"), Some(Location::Memory(0x2F0)));
+        assert_eq!(Location::parse("2f0"), Some(Location::Memory(0x2F0)));
+        assert_eq!(Location::parse("V10"), None);
+        assert_eq!(Location::parse("0x1000"), None);
+        assert_eq!(Location::V(10).to_string(), "VA");
+        assert_eq!(Location::Memory(0x2F0).to_string(), "0x2F0");
+    }
+
+    #[test]
+    fn cheats_search() {
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        memory.memory[0x300] = 3;
+        memory.memory[0x301] = 3;
+        cpu.set_v_register(5, 3);
+        let mut search = MemorySearch::new(Snapshot::new(&memory, &cpu));
+        assert_eq!(search.get_candidates().len(), MEMORY_SIZE + REGISTER_COUNT);
+
+        //lose a life, which is also copied into V5
+        memory.memory[0x300] = 2;
+        cpu.set_v_register(5, 2);
+        search.filter(Snapshot::new(&memory, &cpu), SearchFilter::Decreased);
+        assert_eq!(search.get_candidates(), &[Location::V(5), Location::Memory(0x300)]);
+
+        //nothing happens
+        search.filter(Snapshot::new(&memory, &cpu), SearchFilter::Equal);
+        assert_eq!(search.get_candidates().len(), 2);
+
+        //V5 gets reused for something else
+        cpu.set_v_register(5, 9);
+        search.filter_value(Snapshot::new(&memory, &cpu), 2);
+        assert_eq!(search.get_candidates(), &[Location::Memory(0x300)]);
+        assert_eq!(search.get_value(Location::V(5)), 9);
+    }
+
+    #[test]
+    fn cheats_pin_and_apply() {
+        let mut cheats = CheatList::new("abc");
+        cheats.pin(Location::Memory(0x300), 9, "lives");
+        cheats.pin(Location::V(2), 1, "");
+        cheats.pin(Location::Memory(0x300), 5, "lives");
+        assert_eq!(cheats.get_cheats().len(), 2);
+
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        cheats.apply(&mut memory, &mut cpu);
+        assert_eq!(memory.memory[0x300], 5);
+        assert_eq!(cpu.get_v_registers()[2], 1);
+
+        assert!(cheats.unpin(Location::V(2)));
+        assert!(!cheats.unpin(Location::V(2)));
+    }
+
+    #[test]
+    fn cheats_file() {
+        let mut cheats = CheatList::new("a9993e364706816aba3e25717850c26c9cd0d89d");
+        cheats.pin(Location::V(3), 5, "infinite lives");
+        cheats.pin(Location::Memory(0x2F0), 0x09, "");
+        let text = cheats.to_string();
+        assert_eq!(text, "# chip8 cheats 1\nrom a9993e364706816aba3e25717850c26c9cd0d89d\nV3 05 infinite lives\n0x2F0 09\n");
+        assert_eq!(CheatList::parse(&text), Ok(cheats));
+
+        assert_eq!(CheatList::parse("V3 05"), Err(CheatError::MissingHeader));
+        assert_eq!(CheatList::parse("# chip8 cheats 1\nV3 5G"), Err(CheatError::InvalidLine(2)));
+        assert_eq!(CheatList::parse("# chip8 cheats 1\n\nVG 05"), Err(CheatError::InvalidLine(3)));
+    }
+}
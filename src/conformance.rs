@@ -0,0 +1,355 @@
+// Runs conformance test ROMs, such as the ones in Timendus' chip8-test-suite, under each of the quirk
+// presets and reads the results they draw on screen back as pass or fail. The ROMs aren't bundled
+// with the crate, so they need to be downloaded into a directory first. tests/conformance.rs checks
+// the layouts below against the real ROMs when they are.
+
+use super::cpu::CPUError;
+use super::display::{Display, COLUMNS, ROWS};
+use super::quirks::{QuirkPreset, Quirks, QUIRK_PRESETS};
+use super::Chip8;
+use std::fmt;
+
+//the area that a single result glyph is drawn into
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 5;
+
+//the quirks and keypad tests skip their menus when the choice is written here before they start
+const MENU_CHOICE_ADDRESS: usize = 0x1FF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestOutcome {
+    Pass,
+    Fail,
+    //nothing was drawn where the glyph should be, which usually means the ROM didn't get that far
+    Missing,
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TestOutcome::Pass => write!(f, "pass"),
+            TestOutcome::Fail => write!(f, "FAIL"),
+            TestOutcome::Missing => write!(f, "-"),
+        }
+    }
+}
+
+// Where a ROM draws the result of one of its tests
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphCell {
+    pub name: &'static str,
+    pub x: usize,
+    pub y: usize,
+    //rows the glyph takes up, which is less than GLYPH_HEIGHT where a ROM packs its results tightly
+    pub height: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuChoice {
+    //the ROM just runs
+    None,
+    //the ROM asks which platform to test, which is answered with the preset being run
+    Platform,
+    //the ROM asks which of its tests to run
+    Test(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyInput {
+    Press(u8),
+    Release(u8),
+}
+
+pub struct ConformanceRom {
+    pub name: &'static str,
+    pub file_name: &'static str,
+    pub frames: usize,
+    pub cycles_per_frame: usize,
+    pub menu_choice: MenuChoice,
+    //keys pressed and released at the start of the given frames, for the tests that wait for input
+    pub inputs: Vec<(usize, KeyInput)>,
+    pub cells: Vec<GlyphCell>,
+}
+
+pub struct TestResult {
+    pub name: &'static str,
+    pub outcome: TestOutcome,
+}
+
+// Builds the cells for a ROM that lays its results out in columns, with the glyph drawn after each label,
+// starting from the given row
+fn grid(names: &[&'static str], rows: usize, column_width: usize, row_height: usize, glyph_offset: usize, top: usize) -> Vec<GlyphCell> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| GlyphCell {
+            name,
+            x: (index / rows) * column_width + glyph_offset,
+            y: (index % rows) * row_height + top,
+            height: row_height.min(GLYPH_HEIGHT),
+        })
+        .collect()
+}
+
+// The layouts used by version 4 of the test suite
+pub fn get_test_suite() -> Vec<ConformanceRom> {
+    vec![
+        ConformanceRom {
+            name: "corax+",
+            file_name: "3-corax+.ch8",
+            frames: 60,
+            cycles_per_frame: 1000,
+            menu_choice: MenuChoice::None,
+            inputs: Vec::new(),
+            cells: grid(
+                &[
+                    "3XNN", "4XNN", "5XY0", "7XNN", "9XY0", "1NNN", "8XY0", "8XY1", "8XY2", "8XY3", "8XY4", "8XY5", "8XY7", "8XY6", "8XYE", "FX55", "FX33", "FX1E",
+                ],
+                6,
+                21,
+                5,
+                16,
+                1,
+            ),
+        },
+        ConformanceRom {
+            name: "flags",
+            file_name: "4-flags.ch8",
+            frames: 60,
+            cycles_per_frame: 1000,
+            menu_choice: MenuChoice::None,
+            inputs: Vec::new(),
+            //the happy path, then the same instructions with a carry or borrow, then FX1E's overflow
+            cells: [
+                grid(&["8XY1", "8XY2", "8XY3", "8XY4", "8XY5", "8XY6", "8XY7", "8XYE"], 4, 32, 4, 22, 0),
+                grid(&["carry 8XY4", "carry 8XY5", "carry 8XY6", "carry 8XY7", "carry 8XYE"], 3, 32, 4, 22, 16),
+                grid(&["FX1E overflow"], 1, 32, 4, 22, 28),
+            ]
+            .concat(),
+        },
+        ConformanceRom {
+            name: "quirks",
+            file_name: "5-quirks.ch8",
+            frames: 600,
+            cycles_per_frame: 30,
+            menu_choice: MenuChoice::Platform,
+            inputs: Vec::new(),
+            cells: grid(
+                &["vF reset", "memory", "display wait", "clipping", "shifting", "jumping"],
+                6,
+                COLUMNS,
+                5,
+                COLUMNS - GLYPH_WIDTH,
+                1,
+            ),
+        },
+        //only the FX0A test draws a result, once a key has been pressed and released while it waits
+        ConformanceRom {
+            name: "keypad",
+            file_name: "6-keypad.ch8",
+            frames: 120,
+            cycles_per_frame: 30,
+            menu_choice: MenuChoice::Test(3),
+            inputs: vec![(30, KeyInput::Press(0x5)), (40, KeyInput::Release(0x5))],
+            cells: grid(&["FX0A"], 1, COLUMNS, 5, (COLUMNS - GLYPH_WIDTH) / 2, 14),
+        },
+    ]
+}
+
+// A cross is symmetrical from left to right and a check mark isn't, which tells the two apart without
+// relying on the exact pixels of either glyph
+pub fn classify_glyph(display: &Display, x: usize, y: usize, height: usize) -> TestOutcome {
+    let mut lit = Vec::new();
+    for row in y..(y + height).min(ROWS) {
+        for column in x..(x + GLYPH_WIDTH).min(COLUMNS) {
+            if display.memory[(row * COLUMNS) + column] != 0 {
+                lit.push((column, row));
+            }
+        }
+    }
+
+    let left = match lit.iter().map(|(column, _)| *column).min() {
+        Some(left) => left,
+        None => return TestOutcome::Missing,
+    };
+    let right = lit.iter().map(|(column, _)| *column).max().unwrap();
+    let is_symmetrical = lit.iter().all(|(column, row)| lit.contains(&(left + right - column, *row)));
+    if is_symmetrical {
+        TestOutcome::Fail
+    } else {
+        TestOutcome::Pass
+    }
+}
+
+fn get_platform_number(preset: QuirkPreset) -> u8 {
+    match preset {
+        QuirkPreset::Modern | QuirkPreset::CosmacVip => 1,
+        QuirkPreset::Schip => 2,
+        QuirkPreset::XoChip => 3,
+    }
+}
+
+// Loads the ROM ready to run under the preset
+fn start_rom(rom: &ConformanceRom, program: &[u8], preset: QuirkPreset) -> Result<Chip8, CPUError> {
+    let mut chip8 = Chip8::power_up();
+    if let Err(e) = chip8.try_load_rom(program) {
+        return Err(CPUError::ErrorAccessingMemory(e));
+    }
+    //loading applies the ROM database's quirks for ROMs it knows, which would replace the preset under test
+    chip8.set_quirks(Quirks::new(preset));
+    let choice = match rom.menu_choice {
+        MenuChoice::None => None,
+        MenuChoice::Platform => Some(get_platform_number(preset)),
+        MenuChoice::Test(test) => Some(test),
+    };
+    if let Some(choice) = choice {
+        if let Err(e) = chip8.get_memory_mut().write_byte(MENU_CHOICE_ADDRESS, choice) {
+            return Err(CPUError::ErrorAccessingMemory(e));
+        }
+    }
+    Ok(chip8)
+}
+
+// Runs the ROM for as many frames as it needs, returning the machine so that its display can be read
+pub fn run_rom_to_end(rom: &ConformanceRom, program: &[u8], preset: QuirkPreset) -> Result<Chip8, CPUError> {
+    let mut chip8 = start_rom(rom, program, preset)?;
+
+    for frame in 0..rom.frames {
+        for (_, input) in rom.inputs.iter().filter(|(input_frame, _)| *input_frame == frame) {
+            match *input {
+                KeyInput::Press(key) => chip8.press_key(key),
+                KeyInput::Release(key) => chip8.release_key(key),
+            }
+        }
+        for _ in 0..rom.cycles_per_frame {
+            chip8.step()?;
+        }
+        chip8.decrement_timers();
+    }
+    Ok(chip8)
+}
+
+pub fn run_rom(rom: &ConformanceRom, program: &[u8], preset: QuirkPreset) -> Result<Vec<TestResult>, CPUError> {
+    let chip8 = run_rom_to_end(rom, program, preset)?;
+    Ok(rom
+        .cells
+        .iter()
+        .map(|cell| TestResult {
+            name: cell.name,
+            outcome: classify_glyph(chip8.get_display(), cell.x, cell.y, cell.height),
+        })
+        .collect())
+}
+
+// Runs the ROM under every preset, returning the results in the same order as QUIRK_PRESETS
+pub fn run_all_presets(rom: &ConformanceRom, program: &[u8]) -> Vec<(QuirkPreset, Result<Vec<TestResult>, CPUError>)> {
+    QUIRK_PRESETS.iter().map(|preset| (*preset, run_rom(rom, program, *preset))).collect()
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use super::*;
+
+    const CHECK_MARK: [u8; 5] = [0x01, 0x02, 0x84, 0x48, 0x30];
+    const CROSS: [u8; 5] = [0x88, 0x50, 0x20, 0x50, 0x88];
+
+    #[test]
+    fn conformance_classify_glyph() {
+        let mut display = Display::new();
+        display.draw(&CHECK_MARK, (8, 1));
+        display.draw(&CROSS, (20, 1));
+
+        assert_eq!(classify_glyph(&display, 8, 1, GLYPH_HEIGHT), TestOutcome::Pass);
+        assert_eq!(classify_glyph(&display, 20, 1, GLYPH_HEIGHT), TestOutcome::Fail);
+        assert_eq!(classify_glyph(&display, 40, 1, GLYPH_HEIGHT), TestOutcome::Missing);
+    }
+
+    #[test]
+    fn conformance_run_rom() {
+        //draws a check mark for the vF reset test, but only when 8XY1 resets vF
+        #[rustfmt::skip]
+        let program = [
+            0x6F, 0x01, //LD vF, 0x01
+            0x80, 0x01, //OR v0, v0
+            0xA2, 0x12, //LD I, cross
+            0x3F, 0x00, //SE vF, 0x00
+            0x12, 0x0C, //JP draw
+            0xA2, 0x17, //LD I, check mark
+            0x60, 0x00, //draw: LD v0, 0x00
+            0xD0, 0x05, //DRW v0, v0, 5
+            0x12, 0x10, //JP self
+        ];
+        let program: Vec<u8> = program.iter().chain(CROSS.iter()).chain(CHECK_MARK.iter()).copied().collect();
+        let rom = ConformanceRom {
+            name: "synthetic",
+            file_name: "synthetic.ch8",
+            frames: 2,
+            cycles_per_frame: 20,
+            menu_choice: MenuChoice::None,
+            inputs: Vec::new(),
+            cells: vec![GlyphCell {
+                name: "vF reset",
+                x: 0,
+                y: 0,
+                height: GLYPH_HEIGHT,
+            }],
+        };
+
+        let results = run_all_presets(&rom, &program);
+        let outcomes: Vec<TestOutcome> = results.iter().map(|(_, result)| result.as_ref().unwrap()[0].outcome).collect();
+        assert_eq!(outcomes, vec![TestOutcome::Fail, TestOutcome::Pass, TestOutcome::Fail, TestOutcome::Fail]);
+    }
+
+    #[test]
+    fn conformance_scripted_input() {
+        //waits for a key, then draws a check mark at the top left
+        #[rustfmt::skip]
+        let program = [
+            0xF0, 0x0A, //LD v0, K
+            0xA2, 0x0A, //LD I, check mark
+            0x61, 0x00, //LD v1, 0x00
+            0xD1, 0x15, //DRW v1, v1, 5
+            0x12, 0x08, //JP self
+        ];
+        let program: Vec<u8> = program.iter().chain(CHECK_MARK.iter()).copied().collect();
+        let mut rom = ConformanceRom {
+            name: "synthetic",
+            file_name: "synthetic.ch8",
+            frames: 4,
+            cycles_per_frame: 10,
+            menu_choice: MenuChoice::Test(3),
+            inputs: Vec::new(),
+            cells: vec![GlyphCell {
+                name: "FX0A",
+                x: 0,
+                y: 0,
+                height: GLYPH_HEIGHT,
+            }],
+        };
+        assert_eq!(run_rom(&rom, &program, QuirkPreset::Modern).unwrap()[0].outcome, TestOutcome::Missing);
+
+        rom.inputs = vec![(1, KeyInput::Press(0x5)), (2, KeyInput::Release(0x5))];
+        let mut chip8 = run_rom_to_end(&rom, &program, QuirkPreset::Modern).unwrap();
+        assert_eq!(classify_glyph(chip8.get_display(), 0, 0, GLYPH_HEIGHT), TestOutcome::Pass);
+        assert_eq!(chip8.get_memory_mut().memory[MENU_CHOICE_ADDRESS], 3);
+    }
+
+    #[test]
+    fn conformance_preset_overrides_database() {
+        //Pong is in the embedded ROM database, which would otherwise set its own quirks when it's loaded
+        let program = include_bytes!("../web/roms/PONG");
+        let rom = ConformanceRom {
+            name: "pong",
+            file_name: "PONG",
+            frames: 1,
+            cycles_per_frame: 1,
+            menu_choice: MenuChoice::None,
+            inputs: Vec::new(),
+            cells: Vec::new(),
+        };
+        for preset in QUIRK_PRESETS.iter() {
+            let chip8 = start_rom(&rom, program, *preset).unwrap();
+            assert_eq!(chip8.get_quirks(), Quirks::new(*preset));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Records how every byte of memory was used while a ROM ran: fetched as an instruction, read as
+// data by DXYN and FX65, or written by FX33 and FX55. Bytes that were both written and executed are
+// self-modifying code, and bytes of the ROM that were never touched are either unreachable code or
+// data that wasn't needed.
+
+use super::disassembler::Instruction;
+use super::memory::{Memory, ADDRESS_MASK, MEMORY_SIZE};
+
+pub const EXECUTED: u8 = 0b0001; //fetched as either byte of an instruction
+pub const INSTRUCTION_START: u8 = 0b0010; //fetched as the first byte of an instruction
+pub const READ: u8 = 0b0100;
+pub const WRITTEN: u8 = 0b1000;
+
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { flags: vec![0; MEMORY_SIZE] }
+    }
+
+    // Should be called after every instruction that succeeded, with I as it was before it ran. drew
+    // is false when a DXYN was held back by the display wait quirk.
+    pub fn record(&mut self, pc: u16, opcode: u16, i: u16, drew: bool) {
+        self.mark(pc as usize, EXECUTED | INSTRUCTION_START);
+        self.mark(pc as usize + 1, EXECUTED);
+
+        let (flag, length) = match Instruction::decode(opcode) {
+            Instruction::Drw(_, _, n) if drew => (READ, n as usize),
+            Instruction::Load(x) => (READ, x as usize + 1),
+            Instruction::Store(x) => (WRITTEN, x as usize + 1),
+            Instruction::LdBcd(_) => (WRITTEN, 3),
+            _ => return,
+        };
+        for offset in 0..length {
+            self.mark(i as usize + offset, flag);
+        }
+    }
+
+    pub fn get_flags(&self, address: usize) -> u8 {
+        self.flags[address & ADDRESS_MASK]
+    }
+
+    // One byte of flags for every byte of memory
+    pub fn get_bitmap(&self) -> &[u8] {
+        &self.flags
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.iter_mut().for_each(|flags| *flags = 0);
+    }
+
+    // Lists memory from start up to end, or further if anything past end was touched. Instructions
+    // are disassembled, everything else is shown as data, and runs of untouched bytes are collapsed
+    // into a single line.
+    pub fn get_listing(&self, memory: &Memory, start: usize, end: usize) -> String {
+        let last_touched = self.flags.iter().rposition(|flags| *flags != 0).map_or(0, |address| address + 1);
+        let end = end.max(last_touched).min(MEMORY_SIZE);
+
+        let mut listing = String::from("addr  data  flags\n");
+        let mut address = start;
+        while address < end {
+            let flags = self.flags[address];
+            if flags & INSTRUCTION_START != 0 && address + 1 < MEMORY_SIZE {
+                let opcode = ((memory.memory[address] as u16) << 8) | memory.memory[address + 1] as u16;
+                let flags = flags | self.flags[address + 1];
+                let note = if flags & WRITTEN != 0 { "  ; self-modifying" } else { "" };
+                listing.push_str(&format!(
+                    "{:04X}  {:04X}  {}  {}{}\n",
+                    address,
+                    opcode,
+                    format_flags(flags),
+                    Instruction::decode(opcode),
+                    note
+                ));
+                address += 2;
+            } else if flags == 0 {
+                let run = self.flags[address..end].iter().take_while(|flags| **flags == 0).count();
+                listing.push_str(&format!(
+                    "{:04X}  {:02X}    {}  ; untouched, {} byte(s)\n",
+                    address,
+                    memory.memory[address],
+                    format_flags(0),
+                    run
+                ));
+                address += run;
+            } else {
+                listing.push_str(&format!("{:04X}  {:02X}    {}\n", address, memory.memory[address], format_flags(flags)));
+                address += 1;
+            }
+        }
+        listing
+    }
+
+    fn mark(&mut self, address: usize, flag: u8) {
+        self.flags[address & ADDRESS_MASK] |= flag;
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+fn format_flags(flags: u8) -> String {
+    let letter = |flag: u8, letter: char| if flags & flag != 0 { letter } else { '.' };
+    [letter(EXECUTED, 'X'), letter(READ, 'R'), letter(WRITTEN, 'W')].iter().collect()
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use super::*;
+
+    #[test]
+    fn coverage_record() {
+        let mut coverage = Coverage::new();
+        coverage.record(0x200, 0xD015, 0x300, true);
+        assert_eq!(coverage.get_flags(0x200), EXECUTED | INSTRUCTION_START);
+        assert_eq!(coverage.get_flags(0x201), EXECUTED);
+        assert_eq!(coverage.get_flags(0x304), READ);
+        assert_eq!(coverage.get_flags(0x305), 0);
+
+        coverage.record(0x202, 0xD015, 0x400, false);
+        assert_eq!(coverage.get_flags(0x400), 0);
+
+        coverage.record(0x204, 0xF233, 0xFFF, true);
+        assert_eq!(coverage.get_flags(0xFFF), WRITTEN);
+        assert_eq!(coverage.get_flags(0x000), WRITTEN);
+        assert_eq!(coverage.get_flags(0x001), WRITTEN);
+
+        coverage.record(0x206, 0xF155, 0x202, true);
+        assert_eq!(coverage.get_flags(0x202), EXECUTED | INSTRUCTION_START | WRITTEN);
+
+        coverage.clear();
+        assert!(coverage.get_bitmap().iter().all(|flags| *flags == 0));
+    }
+
+    #[test]
+    fn coverage_listing() {
+        let mut memory = Memory::new();
+        memory.load_program(&[0xA2, 0x08, 0xF0, 0x55, 0x12, 0x04, 0xD0, 0x01, 0xFF, 0x00]).unwrap();
+        let mut coverage = Coverage::new();
+        coverage.record(0x200, 0xA208, 0, true);
+        coverage.record(0x202, 0xF055, 0x204, true);
+        coverage.record(0x204, 0x1204, 0x204, true);
+        coverage.record(0x206, 0xD001, 0x208, true);
+
+        let listing = coverage.get_listing(&memory, 0x200, 0x20A);
+        let expected = "addr  data  flags
+0200  A208  X..  LD I, 0x208
+0202  F055  X..  LD [I], V0
+0204  1204  X.W  JP 0x204  ; self-modifying
+0206  D001  X..  DRW V0, V0, 1
+0208  FF    .R.
+0209  00    ...  ; untouched, 1 byte(s)
+";
+        assert_eq!(listing, expected);
+    }
+}
@@ -1,12 +1,15 @@
 use super::display::Display;
 use super::keyboard::Keyboard;
-use super::memory::{Memory, MemoryError};
+use super::memory::{AccessKind, Memory, MemoryError, MemoryFault, ADDRESS_MASK, PROGRAM_START};
+use super::quirks::{AddressOverflow, Quirks, StackOverflow};
 use super::stack::{Stack, StackError};
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::convert::TryFrom;
 use std::error;
 use std::fmt;
 
-const REGISTER_COUNT: usize = 16;
+pub const REGISTER_COUNT: usize = 16;
 pub struct CPU {
     //program counter
     pc: u16,
@@ -19,22 +22,46 @@ pub struct CPU {
     sound_timer: u8,
     //stack
     stack: Stack,
+    //random number generator used by RND, which can be seeded to make runs reproducible
+    rng: StdRng,
+    //which of the platform specific behaviours to emulate
+    quirks: Quirks,
+    //set by the 60Hz timer tick, and used to hold DXYN until the next frame when the display wait quirk is on
+    vblank: bool,
 }
 
 impl CPU {
     pub fn new() -> Self {
         CPU {
-            pc: 0x200,
+            pc: PROGRAM_START as u16,
             v: [0; REGISTER_COUNT],
             i: 0,
             delay_timer: 0,
             sound_timer: 0,
             stack: Stack::new(),
+            rng: StdRng::from_entropy(),
+            quirks: Quirks::default(),
+            vblank: true,
         }
     }
 
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.stack.configure(quirks.stack_depth, quirks.stack_overflow);
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
     pub fn process_opcode(&mut self, opcode: u16, display: &mut Display, memory: &mut Memory, keyboard: &Keyboard) -> Result<(), CPUError> {
         let mut should_update_pc_after_processing = true;
+        //skipping instructions advance by two instructions instead of one
+        let mut pc_increment = 2;
 
         //get typical opcode values from opcode
         let x = ((opcode & 0x0F00) >> 8) as usize;
@@ -52,12 +79,10 @@ impl CPU {
             //CLS
             (0x0, 0x0, 0xE, 0x0) => display.clear(),
             //RET
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.pc = match self.stack.pop() {
-                    Ok(val) => val,
-                    Err(e) => return Err(CPUError::ErrorAccessingStack(e)),
-                };
-            }
+            (0x0, 0x0, 0xE, 0xE) => match self.pop_return_address(opcode, memory)? {
+                Some(address) => self.pc = address,
+                None => should_update_pc_after_processing = false,
+            },
             //JP addr
             (0x1, _, _, _) => {
                 self.pc = nnn;
@@ -65,131 +90,131 @@ impl CPU {
             }
             //CALL addr
             (0x2, _, _, _) => {
-                match self.stack.push(self.pc) {
-                    Ok(_) => {}
-                    Err(e) => return Err(CPUError::ErrorAccessingStack(e)),
+                if self.push_return_address(opcode, memory)? {
+                    self.pc = nnn;
                 }
                 should_update_pc_after_processing = false;
-                self.pc = nnn;
             }
             //SE Vx byte
             (0x3, _, _, _) => {
                 if self.v[x] == kk {
-                    self.pc = self.pc + 2;
+                    pc_increment = 4;
                 }
             }
             //SNE Vx byte
             (0x4, _, _, _) => {
                 if self.v[x] != kk {
-                    self.pc = self.pc + 2;
+                    pc_increment = 4;
                 }
             }
             //SE Vx Vy
             (0x5, _, _, 0x0) => {
                 if self.v[x] == self.v[y] {
-                    self.pc = self.pc + 2;
+                    pc_increment = 4;
                 }
             }
             //LD Vx byte
             (0x6, _, _, _) => self.v[x] = kk,
             //ADD Vx byte
-            (0x7, _, _, _) => self.v[x] = self.v[x] + kk,
+            (0x7, _, _, _) => self.v[x] = self.v[x].wrapping_add(kk),
             //LD Vx Vy
             (0x8, _, _, 0x0) => self.v[x] = self.v[y],
             //OR Vx Vy
-            (0x8, _, _, 0x1) => self.v[x] = self.v[x] | self.v[y],
+            (0x8, _, _, 0x1) => {
+                self.v[x] |= self.v[y];
+                self.reset_vf();
+            }
             //AND Vx Vy
-            (0x8, _, _, 0x2) => self.v[x] = self.v[x] & self.v[y],
+            (0x8, _, _, 0x2) => {
+                self.v[x] &= self.v[y];
+                self.reset_vf();
+            }
             //XOR Vx Vy
-            (0x8, _, _, 0x3) => self.v[x] = self.v[x] ^ self.v[y],
+            (0x8, _, _, 0x3) => {
+                self.v[x] ^= self.v[y];
+                self.reset_vf();
+            }
             //ADD Vx Vy
             (0x8, _, _, 0x4) => {
-                let res = (self.v[x] as u16) + (self.v[y] as u16);
-                if res > 255 {
-                    self.v[0xF as usize] = 1;
-                }
-                self.v[x] = (res & 0xFF) as u8;
+                let (res, overflow) = self.v[x].overflowing_add(self.v[y]);
+                self.write_result_and_flag(x, res, overflow as u8);
             }
             //SUB Vx Vy
             (0x8, _, _, 0x5) => {
                 let (res, overflow) = self.v[x].overflowing_sub(self.v[y]);
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
-                self.v[x] = res;
+                self.write_result_and_flag(x, res, !overflow as u8);
             }
             //SHR Vx
             (0x8, _, _, 0x6) => {
-                if self.v[x] & 0x1 == 0x1 {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
-                }
-                self.v[x] = self.v[x] >> 1;
+                let source = self.get_shift_source(x, y);
+                self.write_result_and_flag(x, source >> 1, source & 0x1);
             }
             //SUBN Vx Vy
             (0x8, _, _, 0x7) => {
                 let (res, overflow) = self.v[y].overflowing_sub(self.v[x]);
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
-                self.v[x] = res;
+                self.write_result_and_flag(x, res, !overflow as u8);
             }
             //SHL Vx
             (0x8, _, _, 0xE) => {
-                if self.v[x] & 0x80 == 0x80 {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
-                }
-                self.v[x] = self.v[x] << 1;
+                let source = self.get_shift_source(x, y);
+                self.write_result_and_flag(x, source << 1, source >> 7);
             }
             //SNE Vx, Vy
             (0x9, _, _, 0x0) => {
                 if self.v[x] != self.v[y] {
-                    self.pc = self.pc + 2;
+                    pc_increment = 4;
                 }
             }
             //LD I addr
             (0xA, _, _, _) => self.i = nnn,
             //JP V0, addr
-            (0xB, _, _, _) => self.pc = (self.v[0] as u16) + nnn,
+            (0xB, _, _, _) => {
+                let offset = if self.quirks.jump_uses_vx { self.v[x] } else { self.v[0] };
+                self.pc = self.mask_address((offset as usize) + (nnn as usize))?;
+                should_update_pc_after_processing = false;
+            }
             //RND Vx byte
             (0xC, _, _, _) => {
-                let n1: u8 = random();
+                let n1: u8 = self.rng.gen();
                 self.v[x] = n1 & kk;
             }
             //DRW Vx Vy
             (0xD, _, _, _) => {
-                let data = match memory.read_multiple_bytes(self.i as usize, n) {
-                    Ok(data) => data,
-                    Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
-                };
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        //try again next cycle, until the next frame starts
+                        return Ok(());
+                    }
+                    self.vblank = false;
+                }
+                let mut data = Vec::with_capacity(n as usize);
+                for row in 0..(n as usize) {
+                    let address = self.mask_address((self.i as usize) + row)?;
+                    match memory.read_byte(address as usize) {
+                        Ok(byte) => data.push(byte),
+                        Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Read)),
+                    }
+                }
                 let location = (self.v[x] as usize, self.v[y] as usize);
-                println!("LOCATION>>>> {:?}", location);
-                println!("DATA>>>> {:?}", data);
-                self.v[0xF] = display.draw(data, location) as u8;
+                let is_pixel_erased = if self.quirks.clip_sprites {
+                    display.draw_clipped(&data, location)
+                } else {
+                    display.draw(&data, location)
+                };
+                self.v[0xF] = is_pixel_erased as u8;
             }
             //SKP Vx
-            (0xE, _, 0x9, 0xE) => match keyboard.get_key_pressed() {
-                Some(key) => {
-                    if self.v[x] == key {
-                        self.pc = self.pc + 2;
-                    }
+            (0xE, _, 0x9, 0xE) => {
+                if keyboard.get_key_pressed() == Some(self.v[x]) {
+                    pc_increment = 4;
                 }
-                None => {}
-            },
+            }
             //SKNP Vx
-            (0xE, _, 0xA, 0x1) => match keyboard.get_key_pressed() {
-                Some(key) => {
-                    if self.v[x] != key {
-                        self.pc = self.pc + 2;
-                    }
+            (0xE, _, 0xA, 0x1) => {
+                if keyboard.get_key_pressed() != Some(self.v[x]) {
+                    pc_increment = 4;
                 }
-                None => self.pc = self.pc + 2,
-            },
+            }
             //LD Vx DT
             (0xF, _, 0x0, 0x7) => self.v[x] = self.delay_timer,
             //LD Vx K
@@ -202,55 +227,160 @@ impl CPU {
             //LD ST Vx
             (0xF, _, 0x1, 0x8) => self.sound_timer = self.v[x],
             //ADD I Vx
-            (0xF, _, 0x1, 0xE) => self.i = self.i + (self.v[x] as u16),
+            (0xF, _, 0x1, 0xE) => self.i = self.mask_address((self.i as usize) + (self.v[x] as usize))?,
             //LD F Vx
             (0xF, _, 0x2, 0x9) => self.i = memory.get_location_of_font_character(self.v[x]) as u16,
+            //LD HF, Vx
+            (0xF, _, 0x3, 0x0) => self.i = memory.get_location_of_big_font_character(self.v[x]) as u16,
             //LD B Vx
             (0xF, _, 0x3, 0x3) => {
                 let hundreds = self.v[x] / 100;
                 let tens = (self.v[x] - hundreds * 100) / 10;
                 let ones = self.v[x] - hundreds * 100 - tens * 10;
-                match memory.write_byte(self.i as usize, hundreds) {
-                    Ok(_) => {}
-                    Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
-                }
-                match memory.write_byte((self.i + 1) as usize, tens) {
-                    Ok(_) => {}
-                    Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
-                }
-                match memory.write_byte((self.i + 2) as usize, ones) {
-                    Ok(_) => {}
-                    Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
+                for (j, digit) in [hundreds, tens, ones].iter().enumerate() {
+                    let address = self.mask_address((self.i as usize) + j)?;
+                    match memory.write_byte(address as usize, *digit) {
+                        Ok(_) => {}
+                        Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Write)),
+                    }
                 }
             }
             //LD I Vx
             (0xF, _, 0x5, 0x5) => {
                 for j in 0..(x + 1) {
-                    match memory.write_byte((self.i as usize) + j, self.v[j]) {
+                    let address = self.mask_address((self.i as usize) + j)?;
+                    match memory.write_byte(address as usize, self.v[j]) {
                         Ok(_) => {}
-                        Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
+                        Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Write)),
                     }
                 }
+                if self.quirks.memory_increment {
+                    self.i = self.mask_address((self.i as usize) + x + 1)?;
+                }
             }
             //LD Vx I
             (0xF, _, 0x6, 0x5) => {
                 for j in 0..(x + 1) {
-                    self.v[j] = match memory.read_byte((self.i as usize) + j) {
+                    let address = self.mask_address((self.i as usize) + j)?;
+                    self.v[j] = match memory.read_byte(address as usize) {
                         Ok(byte) => byte,
-                        Err(e) => return Err(CPUError::ErrorAccessingMemory(e)),
+                        Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Read)),
                     }
                 }
+                if self.quirks.memory_increment {
+                    self.i = self.mask_address((self.i as usize) + x + 1)?;
+                }
             }
             _ => return Err(CPUError::InvalidOpcodeEncountered(opcode, self.pc)),
         }
 
         if should_update_pc_after_processing {
-            self.pc = self.pc + 2;
+            self.pc = self.mask_address((self.pc as usize) + pc_increment)?;
         }
 
         Ok(())
     }
 
+    //keeps pc and I within the 12 bit address space, either by wrapping or by stopping with an error
+    fn mask_address(&self, address: usize) -> Result<u16, CPUError> {
+        if address <= ADDRESS_MASK {
+            return Ok(address as u16);
+        }
+        match self.quirks.address_overflow {
+            AddressOverflow::Wrap => Ok((address & ADDRESS_MASK) as u16),
+            AddressOverflow::Error => Err(CPUError::AddressOverflow(address, self.pc)),
+        }
+    }
+
+    //pushes pc for CALL, returning false if the stack is full and the overflow quirk halts on it.
+    //Both bytes of an in-memory entry are checked before anything changes, so a CALL that faults can be retried.
+    fn push_return_address(&mut self, opcode: u16, memory: &mut Memory) -> Result<bool, CPUError> {
+        let index = match self.stack.get_push_index() {
+            Ok(index) => index,
+            Err(_) if self.quirks.stack_overflow == StackOverflow::Halt => return Ok(false),
+            Err(e) => return Err(CPUError::ErrorAccessingStack(e)),
+        };
+        if let Some(stack_address) = self.quirks.stack_address {
+            let mut addresses = [0; 2];
+            for (j, address) in addresses.iter_mut().enumerate() {
+                let checked = match get_stack_byte_address(stack_address, index, j) {
+                    Ok(byte_address) => memory.check_write(byte_address as usize).map(|_| byte_address as usize),
+                    Err(byte_address) => Err(MemoryError::InvalidAddress(byte_address)),
+                };
+                match checked {
+                    Ok(byte_address) => *address = byte_address,
+                    Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Write)),
+                }
+            }
+            for (address, byte) in addresses.iter().zip(self.pc.to_be_bytes().iter()) {
+                memory.memory[*address] = *byte;
+            }
+        }
+        self.stack.push(self.pc).map_err(CPUError::ErrorAccessingStack)?;
+        Ok(true)
+    }
+
+    //pops the address RET returns after, or None if the stack is empty and the overflow quirk halts on it.
+    //When the stack is in memory, the address is read back from there, so a ROM can change where it returns to.
+    fn pop_return_address(&mut self, opcode: u16, memory: &Memory) -> Result<Option<u16>, CPUError> {
+        let index = match self.stack.get_pop_index() {
+            Ok(index) => index,
+            Err(_) if self.quirks.stack_overflow == StackOverflow::Halt => return Ok(None),
+            Err(e) => return Err(CPUError::ErrorAccessingStack(e)),
+        };
+        //worked out before popping, so a RET that faults leaves the stack as it was
+        let address = match self.quirks.stack_address {
+            Some(stack_address) => {
+                let mut bytes = [0; 2];
+                for (j, byte) in bytes.iter_mut().enumerate() {
+                    let read = match get_stack_byte_address(stack_address, index, j) {
+                        Ok(address) => memory.read_byte(address as usize),
+                        Err(address) => Err(MemoryError::InvalidAddress(address)),
+                    };
+                    match read {
+                        Ok(value) => *byte = value,
+                        Err(e) => return Err(self.memory_fault(opcode, e, AccessKind::Read)),
+                    }
+                }
+                u16::from_be_bytes(bytes)
+            }
+            None => self.stack.peek().map_err(CPUError::ErrorAccessingStack)?,
+        };
+        let address = self.mask_address(address as usize)?;
+        self.stack.pop().map_err(CPUError::ErrorAccessingStack)?;
+        Ok(Some(address))
+    }
+
+    //a memory access by the instruction at pc that wasn't allowed, which the debugger can stop on
+    fn memory_fault(&self, opcode: u16, error: MemoryError, access: AccessKind) -> CPUError {
+        CPUError::MemoryFault(MemoryFault::new(self.pc, Some(opcode), error, access))
+    }
+
+    //the original hardware writes the result before the flag, so when X is F only the flag is kept
+    fn write_result_and_flag(&mut self, x: usize, result: u8, flag: u8) {
+        if self.quirks.flag_written_first {
+            self.v[0xF] = flag;
+            self.v[x] = result;
+        } else {
+            self.v[x] = result;
+            self.v[0xF] = flag;
+        }
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn get_shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v[y]
+        } else {
+            self.v[x]
+        }
+    }
+
     pub fn get_pc(&self) -> u16 {
         self.pc
     }
@@ -259,10 +389,19 @@ impl CPU {
         &self.v[..]
     }
 
+    pub fn set_v_register(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
     pub fn get_i(&self) -> u16 {
         self.i
     }
 
+    //the addresses of the CALL instructions that haven't returned yet, oldest first
+    pub fn get_stack(&self) -> &[u16] {
+        self.stack.get_entries()
+    }
+
     pub fn get_delay_timer(&self) -> u8 {
         self.delay_timer
     }
@@ -271,38 +410,54 @@ impl CPU {
         self.sound_timer
     }
 
-    pub fn clear_pc(&mut self) {
-        self.pc = 0x200;
-    }
-
-    pub fn clear_i(&mut self) {
+    // Puts the CPU back the way it powers up, keeping the quirks and the random number generator
+    pub fn reset(&mut self) {
+        self.pc = PROGRAM_START as u16;
+        self.v = [0; REGISTER_COUNT];
         self.i = 0;
-    }
-
-    pub fn clear_delay_timer(&mut self) {
         self.delay_timer = 0;
-    }
-
-    pub fn clear_sound_timer(&mut self) {
         self.sound_timer = 0;
+        self.stack.clear();
+        self.vblank = true;
     }
 
     pub fn decrement_timers(&mut self) {
+        self.vblank = true;
+
         if self.delay_timer > 0 {
-            self.delay_timer = self.delay_timer - 1;
+            self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            self.sound_timer = self.sound_timer - 1;
+            self.sound_timer -= 1;
         }
     }
 }
 
+// Where a byte of a stack entry is kept when the stack is in memory. A stack too close to the end of the
+// address space gives an Err with the address past the end, rather than wrapping around to the start.
+fn get_stack_byte_address(stack_address: u16, index: usize, byte: usize) -> Result<u16, usize> {
+    index
+        .checked_mul(2)
+        .and_then(|offset| offset.checked_add(byte))
+        .and_then(|offset| u16::try_from(offset).ok())
+        .and_then(|offset| stack_address.checked_add(offset))
+        .ok_or_else(|| (stack_address as usize).saturating_add(index.saturating_mul(2)).saturating_add(byte))
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
+}
+
 #[derive(Debug)]
 pub enum CPUError {
     ErrorAccessingStack(StackError),
     ErrorAccessingMemory(MemoryError),
     InvalidOpcodeEncountered(u16, u16),
+    AddressOverflow(usize, u16),
+    MemoryFault(MemoryFault),
 }
 
 impl fmt::Display for CPUError {
@@ -311,6 +466,8 @@ impl fmt::Display for CPUError {
             CPUError::ErrorAccessingStack(ref e) => e.fmt(f),
             CPUError::ErrorAccessingMemory(ref e) => e.fmt(f),
             CPUError::InvalidOpcodeEncountered(opcode, addr) => write!(f, "Unknown opcode encountered as addr {:#04X}: {:#04X}", opcode, addr),
+            CPUError::AddressOverflow(address, addr) => write!(f, "Address {:#04X} is past the end of memory at addr {:#04X}", address, addr),
+            CPUError::MemoryFault(ref fault) => fault.fmt(f),
         }
     }
 }
@@ -321,6 +478,8 @@ impl error::Error for CPUError {
             CPUError::ErrorAccessingStack(ref e) => Some(e),
             CPUError::ErrorAccessingMemory(ref e) => Some(e),
             CPUError::InvalidOpcodeEncountered(_opcode, _addr) => None,
+            CPUError::AddressOverflow(_address, _addr) => None,
+            CPUError::MemoryFault(ref _fault) => None,
         }
     }
 }
@@ -328,6 +487,10 @@ impl error::Error for CPUError {
 #[cfg(test)]
 mod cpu_tests {
     use super::*;
+    use crate::display::COLUMNS;
+    use crate::font::FontSet;
+    use crate::memory::{FaultCause, Protection};
+    use crate::quirks::QuirkPreset;
 
     #[test]
     fn cpu_call_ret() {
@@ -432,6 +595,21 @@ mod cpu_tests {
         assert_eq!(cpu.pc, 0x204);
     }
 
+    #[test]
+    fn cpu_add_vx_byte_wraps() {
+        let mut cpu = CPU::new();
+        let mut display = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[8] = 0x44;
+        let opcode = 0x78FF; //add 0xFF to v[8], which wraps around (games use this to decrement)
+        cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.v[8], 0x43);
+        //unlike 8XY4, the carry isn't kept
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
     #[test]
     fn cpu_ld_vx_vy() {
         let mut cpu = CPU::new();
@@ -537,6 +715,12 @@ mod cpu_tests {
 
         assert_eq!(cpu.v[1], 0x00);
         assert_eq!(cpu.v[0xF], 1);
+
+        let opcode = 0x8124; //add v[1] and v[2] again, which doesn't carry and so clears v[F]
+        cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
+
+        assert_eq!(cpu.v[1], 0x01);
+        assert_eq!(cpu.v[0xF], 0);
     }
 
     #[test]
@@ -678,7 +862,7 @@ mod cpu_tests {
 
         let opcode = 0xB1AF; //jump to address v[0] + 0x1AF (0x22 + 0x1AF = 0x1D1)
         cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
-        assert_eq!(cpu.pc, 0x1D3);
+        assert_eq!(cpu.pc, 0x1D1);
     }
 
     // This test might fail, if the random number generated happens to be 0x22.
@@ -701,6 +885,29 @@ mod cpu_tests {
         assert_ne!(cpu.v[0], 0x22);
     }
 
+    #[test]
+    fn cpu_rnd_seeded() {
+        let mut display = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        let mut random_numbers = |seed: u64, opcode: u16| {
+            let mut cpu = CPU::new();
+            cpu.seed_rng(seed);
+            (0..16)
+                .map(|_| {
+                    cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
+                    cpu.v[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        //the same seed gives the same numbers every run
+        assert_eq!(random_numbers(0xC8, 0xC0FF), random_numbers(0xC8, 0xC0FF));
+        assert_ne!(random_numbers(0xC8, 0xC0FF), random_numbers(0xC9, 0xC0FF));
+        //and they're masked with kk
+        assert!(random_numbers(0xC8, 0xC00F).iter().all(|number| *number <= 0x0F));
+    }
+
     #[test]
     fn cpu_drw_vx_vy_n() {
         let mut cpu = CPU::new();
@@ -846,6 +1053,17 @@ mod cpu_tests {
         let opcode = 0xF029; //load address of font character in v[0] into I
         cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
         assert_eq!(cpu.i, 0x0A);
+
+        let opcode = 0xF030; //load address of big font character in v[0] into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0x64);
+
+        //the font can be moved
+        memory.set_font(FontSet::Standard, 0x50).unwrap();
+        cpu.process_opcode(0xF029, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0x5A);
+        cpu.process_opcode(0xF030, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0xB4);
     }
 
     #[test]
@@ -1026,4 +1244,363 @@ mod cpu_tests {
         assert_eq!(cpu.v[0xE], 0x00);
         assert_eq!(cpu.v[0xF], 0x00);
     }
+
+    #[test]
+    fn cpu_quirks() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks::new(QuirkPreset::CosmacVip));
+
+        let opcode = 0x6F01; //load 0x01 into v[F]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x8121; //or v[1] and v[2], which resets v[F]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.v[0xF], 0);
+
+        let opcode = 0x6281; //load 0x81 into v[2]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x8126; //shift v[2] right into v[1]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.v[1], 0x40);
+        assert_eq!(cpu.v[2], 0x81);
+        assert_eq!(cpu.v[0xF], 1);
+
+        let opcode = 0xA400; //load the value 0x400 into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xF255; //store v[0]..v[2], leaving I past v[2]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0x403);
+
+        //only one sprite can be drawn each frame
+        let pc = cpu.pc;
+        let opcode = 0xD001;
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, pc + 2);
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, pc + 2);
+        cpu.decrement_timers();
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, pc + 4);
+
+        cpu.set_quirks(Quirks::new(QuirkPreset::Schip));
+        let opcode = 0x6210; //load 0x10 into v[2]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xB234; //jump to address v[2] + 0x234
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x244);
+    }
+
+    #[test]
+    fn cpu_address_wrapping() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+
+        let opcode = 0x1FFE; //jump to the last instruction in memory
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x6001; //load 0x01 into v[0], which moves the pc back to the start of memory
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x000);
+
+        let opcode = 0x1FFE; //jump to the last instruction in memory
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x3001; //skip the next instruction (condition v[0] == 0x01 is true)
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x002);
+
+        let opcode = 0xBFFF; //jump to address v[0] + 0xFFF
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x000);
+
+        let opcode = 0xAFFF; //load the value 0xFFF into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xF01E; //add v[0] to I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0x000);
+
+        let opcode = 0xAFFE; //load the value 0xFFE into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x60FF; //load 0xFF into v[0]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xF033; //store the BCD of v[0], which runs past the end of memory
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(memory.memory[0xFFE], 2);
+        assert_eq!(memory.memory[0xFFF], 5);
+        assert_eq!(memory.memory[0x000], 5);
+
+        let opcode = 0x6100; //load 0x00 into v[1]
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xD113; //draw a sprite read from 0xFFE, 0xFFF and the start of memory at (0, 0)
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(disp.memory[6], 1);
+        assert_eq!(disp.memory[COLUMNS + 5], 1);
+        assert_eq!(disp.memory[(COLUMNS * 2) + 5], 1);
+    }
+
+    #[test]
+    fn cpu_address_overflow_error() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks {
+            address_overflow: AddressOverflow::Error,
+            ..Quirks::default()
+        });
+
+        let opcode = 0x1FFE; //jump to the last instruction in memory
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0x6001; //load 0x01 into v[0]
+        let result = cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::AddressOverflow(0x1000, 0xFFE))));
+
+        let opcode = 0x1200; //jump back to the start of the program
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+
+        let opcode = 0xBFFF; //jump to address v[0] + 0xFFF
+        let result = cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::AddressOverflow(0x1000, _))));
+
+        let opcode = 0xAFFF; //load the value 0xFFF into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xF01E; //add v[0] to I
+        let result = cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::AddressOverflow(0x1000, _))));
+
+        let opcode = 0xF155; //store v[0] and v[1], the second of which is past the end of memory
+        let result = cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::AddressOverflow(0x1000, _))));
+    }
+
+    //the result and flag of each flag setting instruction, worked out independently of the CPU
+    fn expected_result_and_flag(operation: u16, vx: u8, vy: u8) -> (u8, u8) {
+        let (vx, vy) = (vx as u16, vy as u16);
+        let (result, flag) = match operation {
+            0x4 => (vx + vy, (vx + vy > 0xFF) as u16),
+            0x5 => (vx + 0x100 - vy, (vx >= vy) as u16),
+            0x6 => (vx >> 1, vx & 0x1),
+            0x7 => (vy + 0x100 - vx, (vy >= vx) as u16),
+            0xE => (vx << 1, vx >> 7),
+            _ => unreachable!(),
+        };
+        ((result & 0xFF) as u8, flag as u8)
+    }
+
+    #[test]
+    fn cpu_flag_ordering() {
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+
+        for flag_written_first in [false, true].iter() {
+            let mut cpu = CPU::new();
+            cpu.set_quirks(Quirks {
+                flag_written_first: *flag_written_first,
+                ..Quirks::default()
+            });
+
+            for operation in [0x4, 0x5, 0x6, 0x7, 0xE].iter() {
+                for a in 0..=0xFF {
+                    for b in 0..=0xFF {
+                        let (result, flag) = expected_result_and_flag(*operation, a, b);
+
+                        //X is F, so the result and the flag both go to v[F] and the one written last is kept
+                        cpu.v[0xF] = a;
+                        cpu.v[0x1] = b;
+                        cpu.process_opcode(0x8F10 | operation, &mut disp, &mut memory, &keyboard).unwrap();
+                        let expected = if *flag_written_first { result } else { flag };
+                        assert_eq!(cpu.v[0xF], expected, "8F1{:X} with v[F] = {:#04X} and v[1] = {:#04X}", operation, a, b);
+
+                        //Y is F, so v[F] is read as an operand before being overwritten by the flag
+                        cpu.v[0x1] = a;
+                        cpu.v[0xF] = b;
+                        cpu.process_opcode(0x81F0 | operation, &mut disp, &mut memory, &keyboard).unwrap();
+                        assert_eq!(cpu.v[0x1], result, "81F{:X} with v[1] = {:#04X} and v[F] = {:#04X}", operation, a, b);
+                        assert_eq!(cpu.v[0xF], flag, "81F{:X} with v[1] = {:#04X} and v[F] = {:#04X}", operation, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cpu_memory_fault() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        memory.protect(0x000, 0x1FF, Protection::ReadOnly);
+
+        let opcode = 0xA04B; //load the address of the F character into I
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xD011; //draw its first row, which only reads the font
+        cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        let opcode = 0xF155; //store v[0] and v[1] over it
+        let result = cpu.process_opcode(opcode, &mut disp, &mut memory, &keyboard);
+        let expected = MemoryFault {
+            pc: 0x204,
+            opcode: Some(0xF155),
+            address: 0x04B,
+            access: AccessKind::Write,
+            cause: FaultCause::ProtectionViolation,
+        };
+        assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault == expected));
+        assert_eq!(cpu.get_pc(), 0x204);
+        assert_eq!(memory.memory[0x04B], 0xF0);
+    }
+
+    #[test]
+    fn cpu_stack_depth() {
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+
+        //every level is usable, and the one after the last overflows
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks::new(QuirkPreset::CosmacVip));
+        for _ in 0..12 {
+            cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard).unwrap();
+        }
+        let result = cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::ErrorAccessingStack(StackError::PushToFullStack))));
+
+        //halting leaves pc on the instruction that overflowed
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks {
+            stack_depth: 1,
+            stack_overflow: StackOverflow::Halt,
+            ..Quirks::default()
+        });
+        cpu.process_opcode(0x2400, &mut disp, &mut memory, &keyboard).unwrap();
+        cpu.process_opcode(0x2500, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.get_pc(), 0x400);
+        assert_eq!(cpu.get_stack(), &[0x200]);
+        cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard).unwrap();
+        cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn cpu_reset() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks::new(QuirkPreset::CosmacVip));
+
+        for opcode in [0x6A05, 0xA300, 0xFA15, 0x2400].iter() {
+            cpu.process_opcode(*opcode, &mut disp, &mut memory, &keyboard).unwrap();
+        }
+        cpu.reset();
+        assert_eq!(cpu.get_pc(), PROGRAM_START as u16);
+        assert_eq!(cpu.get_v_registers(), &[0; REGISTER_COUNT]);
+        assert_eq!(cpu.get_i(), 0);
+        assert_eq!(cpu.get_delay_timer(), 0);
+        assert!(cpu.get_stack().is_empty());
+        assert_eq!(cpu.get_quirks(), Quirks::new(QuirkPreset::CosmacVip));
+    }
+
+    #[test]
+    fn cpu_stack_in_memory() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks {
+            stack_address: Some(0xEA0),
+            ..Quirks::default()
+        });
+
+        cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard).unwrap();
+        cpu.process_opcode(0x2400, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(memory.read_multiple_bytes(0xEA0, 4).unwrap(), &[0x02, 0x00, 0x03, 0x00]);
+
+        //the ROM changes where the second call returns to
+        memory.memory[0xEA3] = 0x10;
+        cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.get_pc(), 0x312);
+
+        memory.protect(0xEA2, 0xEA3, Protection::ReadOnly);
+        let result = cpu.process_opcode(0x2500, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault.address == 0xEA2));
+    }
+
+    #[test]
+    fn cpu_stack_fault_retry() {
+        let mut cpu = CPU::new();
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks {
+            stack_address: Some(0xEA0),
+            ..Quirks::default()
+        });
+        cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard).unwrap();
+
+        //a CALL that faults leaves the stack alone, however often it's retried
+        memory.protect(0xEA3, 0xEA3, Protection::ReadOnly);
+        for _ in 0..2 {
+            let result = cpu.process_opcode(0x2400, &mut disp, &mut memory, &keyboard);
+            assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault.address == 0xEA3));
+            assert_eq!(cpu.get_stack(), &[0x200]);
+            assert_eq!(memory.memory[0xEA2], 0);
+            assert_eq!(cpu.get_pc(), 0x300);
+        }
+        memory.clear_protection();
+        cpu.process_opcode(0x2400, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.get_stack(), &[0x200, 0x300]);
+
+        //and so does a RET
+        memory.protect(0xEA2, 0xEA3, Protection::NoAccess);
+        for _ in 0..2 {
+            let result = cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard);
+            assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault.address == 0xEA2));
+            assert_eq!(cpu.get_stack(), &[0x200, 0x300]);
+            assert_eq!(cpu.get_pc(), 0x400);
+        }
+        memory.clear_protection();
+        cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard).unwrap();
+        assert_eq!(cpu.get_stack(), &[0x200]);
+        assert_eq!(cpu.get_pc(), 0x302);
+
+        //a return address the address overflow quirk rejects doesn't pop either
+        cpu.set_quirks(Quirks {
+            address_overflow: AddressOverflow::Error,
+            ..Quirks::default()
+        });
+        cpu.process_opcode(0x2500, &mut disp, &mut memory, &keyboard).unwrap();
+        cpu.stack.pop().unwrap();
+        cpu.stack.push(0x1FFE).unwrap();
+        assert!(cpu.process_opcode(0x00EE, &mut disp, &mut memory, &keyboard).is_err());
+        assert_eq!(cpu.get_stack().last(), Some(&0x1FFE));
+    }
+
+    #[test]
+    fn cpu_stack_past_end_of_memory() {
+        let mut disp = Display::new();
+        let mut memory = Memory::new();
+        let keyboard = Keyboard::new();
+        //the second byte of the first entry is the first one past the end of memory
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks {
+            stack_address: Some(0xFFF),
+            ..Quirks::default()
+        });
+        let result = cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault.address == 0x1000 && fault.cause == FaultCause::OutOfRange));
+
+        //the second entry is past the end of the address space, which mustn't wrap around to 0x0000
+        let mut cpu = CPU::new();
+        cpu.set_quirks(Quirks {
+            stack_address: Some(0xFFFE),
+            ..Quirks::default()
+        });
+        let result = cpu.process_opcode(0x2300, &mut disp, &mut memory, &keyboard);
+        assert!(matches!(result, Err(CPUError::MemoryFault(fault)) if fault.address == 0xFFFE));
+        assert_eq!(get_stack_byte_address(0xFFFE, 1, 0), Err(0x10000));
+        assert_eq!(get_stack_byte_address(0xEA0, 1, 1), Ok(0xEA3));
+    }
 }
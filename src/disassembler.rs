@@ -0,0 +1,254 @@
+// Decodes opcodes into instructions, for tracing and for showing listings of a ROM. The CPU has its
+// own decoding in process_opcode, so this is only used for looking at programs, not running them.
+
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+// Broad groups of instructions, used to filter traces and to group profiling results
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OpcodeClass {
+    Display = 0,
+    Flow = 1,
+    Skip = 2,
+    Load = 3,
+    Arithmetic = 4,
+    Memory = 5,
+    Input = 6,
+    Timer = 7,
+    Random = 8,
+    Invalid = 9,
+}
+
+pub const OPCODE_CLASSES: [OpcodeClass; 10] = [
+    OpcodeClass::Display,
+    OpcodeClass::Flow,
+    OpcodeClass::Skip,
+    OpcodeClass::Load,
+    OpcodeClass::Arithmetic,
+    OpcodeClass::Memory,
+    OpcodeClass::Input,
+    OpcodeClass::Timer,
+    OpcodeClass::Random,
+    OpcodeClass::Invalid,
+];
+
+impl OpcodeClass {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            OpcodeClass::Display => "display",
+            OpcodeClass::Flow => "flow",
+            OpcodeClass::Skip => "skip",
+            OpcodeClass::Load => "load",
+            OpcodeClass::Arithmetic => "arithmetic",
+            OpcodeClass::Memory => "memory",
+            OpcodeClass::Input => "input",
+            OpcodeClass::Timer => "timer",
+            OpcodeClass::Random => "random",
+            OpcodeClass::Invalid => "invalid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        OPCODE_CLASSES.iter().find(|class| class.get_name() == name).copied()
+    }
+}
+
+// Registers are stored as their index, so V5 is 5
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    Cls,
+    Ret,
+    Jp(u16),
+    Call(u16),
+    SeByte(u8, u8),
+    SneByte(u8, u8),
+    SeRegister(u8, u8),
+    LdByte(u8, u8),
+    AddByte(u8, u8),
+    LdRegister(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    AddRegister(u8, u8),
+    Sub(u8, u8),
+    Shr(u8, u8),
+    Subn(u8, u8),
+    Shl(u8, u8),
+    SneRegister(u8, u8),
+    LdI(u16),
+    JpV0(u16),
+    Rnd(u8, u8),
+    Drw(u8, u8, u8),
+    Skp(u8),
+    Sknp(u8),
+    LdFromDelayTimer(u8),
+    LdKey(u8),
+    LdDelayTimer(u8),
+    LdSoundTimer(u8),
+    AddI(u8),
+    LdFont(u8),
+    LdBigFont(u8),
+    LdBcd(u8),
+    Store(u8),
+    Load(u8),
+    Invalid(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Self {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let nnn = opcode & 0x0FFF;
+        let kk = (opcode & 0x00FF) as u8;
+        let n = (opcode & 0x000F) as u8;
+
+        match ((opcode & 0xF000) >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+            (0x1, _, _, _) => Instruction::Jp(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SeByte(x, kk),
+            (0x4, _, _, _) => Instruction::SneByte(x, kk),
+            (0x5, _, _, 0x0) => Instruction::SeRegister(x, y),
+            (0x6, _, _, _) => Instruction::LdByte(x, kk),
+            (0x7, _, _, _) => Instruction::AddByte(x, kk),
+            (0x8, _, _, 0x0) => Instruction::LdRegister(x, y),
+            (0x8, _, _, 0x1) => Instruction::Or(x, y),
+            (0x8, _, _, 0x2) => Instruction::And(x, y),
+            (0x8, _, _, 0x3) => Instruction::Xor(x, y),
+            (0x8, _, _, 0x4) => Instruction::AddRegister(x, y),
+            (0x8, _, _, 0x5) => Instruction::Sub(x, y),
+            (0x8, _, _, 0x6) => Instruction::Shr(x, y),
+            (0x8, _, _, 0x7) => Instruction::Subn(x, y),
+            (0x8, _, _, 0xE) => Instruction::Shl(x, y),
+            (0x9, _, _, 0x0) => Instruction::SneRegister(x, y),
+            (0xA, _, _, _) => Instruction::LdI(nnn),
+            (0xB, _, _, _) => Instruction::JpV0(nnn),
+            (0xC, _, _, _) => Instruction::Rnd(x, kk),
+            (0xD, _, _, _) => Instruction::Drw(x, y, n),
+            (0xE, _, 0x9, 0xE) => Instruction::Skp(x),
+            (0xE, _, 0xA, 0x1) => Instruction::Sknp(x),
+            (0xF, _, 0x0, 0x7) => Instruction::LdFromDelayTimer(x),
+            (0xF, _, 0x0, 0xA) => Instruction::LdKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::LdDelayTimer(x),
+            (0xF, _, 0x1, 0x8) => Instruction::LdSoundTimer(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LdFont(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LdBigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::LdBcd(x),
+            (0xF, _, 0x5, 0x5) => Instruction::Store(x),
+            (0xF, _, 0x6, 0x5) => Instruction::Load(x),
+            _ => Instruction::Invalid(opcode),
+        }
+    }
+
+    pub fn get_class(&self) -> OpcodeClass {
+        match self {
+            Instruction::Cls | Instruction::Drw(..) => OpcodeClass::Display,
+            Instruction::Ret | Instruction::Jp(_) | Instruction::Call(_) | Instruction::JpV0(_) => OpcodeClass::Flow,
+            Instruction::SeByte(..) | Instruction::SneByte(..) | Instruction::SeRegister(..) | Instruction::SneRegister(..) => OpcodeClass::Skip,
+            Instruction::LdByte(..) | Instruction::LdRegister(..) | Instruction::LdI(_) | Instruction::LdFont(_) | Instruction::LdBigFont(_) => OpcodeClass::Load,
+            Instruction::AddByte(..)
+            | Instruction::Or(..)
+            | Instruction::And(..)
+            | Instruction::Xor(..)
+            | Instruction::AddRegister(..)
+            | Instruction::Sub(..)
+            | Instruction::Shr(..)
+            | Instruction::Subn(..)
+            | Instruction::Shl(..)
+            | Instruction::AddI(_) => OpcodeClass::Arithmetic,
+            Instruction::LdBcd(_) | Instruction::Store(_) | Instruction::Load(_) => OpcodeClass::Memory,
+            Instruction::Skp(_) | Instruction::Sknp(_) | Instruction::LdKey(_) => OpcodeClass::Input,
+            Instruction::LdFromDelayTimer(_) | Instruction::LdDelayTimer(_) | Instruction::LdSoundTimer(_) => OpcodeClass::Timer,
+            Instruction::Rnd(..) => OpcodeClass::Random,
+            Instruction::Invalid(_) => OpcodeClass::Invalid,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Jp(address) => write!(f, "JP {:#05X}", address),
+            Instruction::Call(address) => write!(f, "CALL {:#05X}", address),
+            Instruction::SeByte(x, byte) => write!(f, "SE V{:X}, {:#04X}", x, byte),
+            Instruction::SneByte(x, byte) => write!(f, "SNE V{:X}, {:#04X}", x, byte),
+            Instruction::SeRegister(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LdByte(x, byte) => write!(f, "LD V{:X}, {:#04X}", x, byte),
+            Instruction::AddByte(x, byte) => write!(f, "ADD V{:X}, {:#04X}", x, byte),
+            Instruction::LdRegister(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddRegister(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneRegister(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(address) => write!(f, "LD I, {:#05X}", address),
+            Instruction::JpV0(address) => write!(f, "JP V0, {:#05X}", address),
+            Instruction::Rnd(x, byte) => write!(f, "RND V{:X}, {:#04X}", x, byte),
+            Instruction::Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LdFromDelayTimer(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDelayTimer(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdSoundTimer(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdFont(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LdBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdBcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::Invalid(opcode) => write!(f, "DW {:#06X}", opcode),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn disassemble(opcode: u16) -> String {
+    Instruction::decode(opcode).to_string()
+}
+
+#[cfg(test)]
+mod disassembler_tests {
+    use super::*;
+
+    #[test]
+    fn disassembler_decode() {
+        assert_eq!(Instruction::decode(0x00E0), Instruction::Cls);
+        assert_eq!(Instruction::decode(0x1234), Instruction::Jp(0x234));
+        assert_eq!(Instruction::decode(0x8AB6), Instruction::Shr(0xA, 0xB));
+        assert_eq!(Instruction::decode(0xD125), Instruction::Drw(1, 2, 5));
+        assert_eq!(Instruction::decode(0xF465), Instruction::Load(4));
+        assert_eq!(Instruction::decode(0x5121), Instruction::Invalid(0x5121));
+        assert_eq!(Instruction::decode(0x0123), Instruction::Invalid(0x0123));
+    }
+
+    #[test]
+    fn disassembler_text() {
+        assert_eq!(disassemble(0x6A02), "LD VA, 0x02");
+        assert_eq!(disassemble(0xA22A), "LD I, 0x22A");
+        assert_eq!(disassemble(0xD01F), "DRW V0, V1, 15");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0xF530), "LD HF, V5");
+        assert_eq!(disassemble(0xFFFF), "DW 0xFFFF");
+    }
+
+    #[test]
+    fn disassembler_classes() {
+        assert_eq!(Instruction::decode(0xD01F).get_class(), OpcodeClass::Display);
+        assert_eq!(Instruction::decode(0x2300).get_class(), OpcodeClass::Flow);
+        assert_eq!(Instruction::decode(0xE19E).get_class(), OpcodeClass::Input);
+        assert_eq!(Instruction::decode(0xC0FF).get_class(), OpcodeClass::Random);
+        for class in OPCODE_CLASSES.iter() {
+            assert_eq!(OpcodeClass::from_name(class.get_name()), Some(*class));
+        }
+    }
+}
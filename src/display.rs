@@ -1,62 +1,266 @@
+use super::palette::{Colour, Palette};
+use super::phosphor::{Phosphor, PhosphorMode, FULL_INTENSITY};
+use super::png;
 use std::fmt;
+use wasm_bindgen::prelude::*;
 
 pub const COLUMNS: usize = 64;
 pub const ROWS: usize = 32;
 pub const DISPLAY_MEMORY_SIZE: usize = COLUMNS * ROWS;
+pub const BYTES_PER_RGBA_PIXEL: usize = 4;
+pub const MAX_SCALE: usize = 32; //a 2048x1024 image, which is already larger than most screens
+pub const MAX_BLEND_FRAMES: usize = 60; //a second of history, by which point the blend is a smear
+
+//the area of the display that has changed, in display pixels rather than scaled rgba pixels
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirtyRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRegion {
+    pub fn full_screen() -> Self {
+        DirtyRegion {
+            x: 0,
+            y: 0,
+            width: COLUMNS,
+            height: ROWS,
+        }
+    }
+
+    //grows the region to include the given pixel
+    fn include(&mut self, x: usize, y: usize) {
+        let right = (self.x + self.width).max(x + 1);
+        let bottom = (self.y + self.height).max(y + 1);
+        self.x = self.x.min(x);
+        self.y = self.y.min(y);
+        self.width = right - self.x;
+        self.height = bottom - self.y;
+    }
+}
 
 pub struct Display {
     pub memory: [u8; DISPLAY_MEMORY_SIZE],
+    palette: Palette,
+    scale: usize,
+    rgba: Vec<u8>,
+    phosphor: Option<Phosphor>,
+    dirty_region: Option<DirtyRegion>,
 }
 
 impl Display {
     pub fn new() -> Self {
-        Display { memory: [0; DISPLAY_MEMORY_SIZE] }
+        Display {
+            memory: [0; DISPLAY_MEMORY_SIZE],
+            palette: Palette::default(),
+            scale: 1,
+            rgba: vec![0; DISPLAY_MEMORY_SIZE * BYTES_PER_RGBA_PIXEL],
+            phosphor: None,
+            //the frontend has never drawn anything, so everything needs to be drawn the first time
+            dirty_region: Some(DirtyRegion::full_screen()),
+        }
     }
 
     pub fn clear(&mut self) {
+        if self.memory.iter().any(|pixel| *pixel != 0) {
+            self.mark_dirty(None);
+        }
         self.memory = [0; DISPLAY_MEMORY_SIZE];
     }
 
     pub fn draw(&mut self, sprite: &[u8], location: (usize, usize)) -> bool {
+        self.draw_sprite(sprite, location, false)
+    }
+
+    //the sprite still starts at a wrapped location, but anything past the edge of the screen is cut off
+    pub fn draw_clipped(&mut self, sprite: &[u8], location: (usize, usize)) -> bool {
+        self.draw_sprite(sprite, location, true)
+    }
+
+    fn draw_sprite(&mut self, sprite: &[u8], location: (usize, usize), clip: bool) -> bool {
         let (x, y) = location;
         let x = self.wrap(x, COLUMNS);
         let y = self.wrap(y, ROWS);
         let mut is_pixel_erased = false;
-        for (i, byte) in sprite.into_iter().enumerate() {
+        for (i, byte) in sprite.iter().enumerate() {
+            if clip && y + i >= ROWS {
+                break;
+            }
             let yi = self.wrap(y + i, ROWS);
             for j in 0..8 {
+                if clip && x + j >= COLUMNS {
+                    break;
+                }
                 let xi = self.wrap(x + j, COLUMNS);
                 let insert_location = (COLUMNS * yi) + xi;
                 let bit = (*byte >> (7 - j)) & 0x01;
-                if self.memory[insert_location] != bit {
+                //0 bits leave the pixel under them alone
+                if bit == 0 {
+                    continue;
+                }
+                if self.memory[insert_location] == 0 {
                     self.memory[insert_location] = 1;
-                } else {
-                    if self.memory[insert_location] == 1 {
-                        self.memory[insert_location] = 0;
-                        is_pixel_erased = true;
+                    if let Some(phosphor) = self.phosphor.as_mut() {
+                        phosphor.light_pixel(insert_location);
                     }
+                } else {
+                    self.memory[insert_location] = 0;
+                    is_pixel_erased = true;
                 }
+                self.mark_dirty(Some((xi, yi)));
             }
         }
         is_pixel_erased
     }
 
+    //passing None marks the whole display as dirty
+    pub fn mark_dirty(&mut self, pixel: Option<(usize, usize)>) {
+        self.dirty_region = match (self.dirty_region, pixel) {
+            (_, None) => Some(DirtyRegion::full_screen()),
+            (None, Some((x, y))) => Some(DirtyRegion { x, y, width: 1, height: 1 }),
+            (Some(mut region), Some((x, y))) => {
+                region.include(x, y);
+                Some(region)
+            }
+        };
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_region.is_some()
+    }
+
+    //returns the area that changed since the last time this was called, if anything changed at all
+    pub fn take_dirty_region(&mut self) -> Option<DirtyRegion> {
+        self.dirty_region.take()
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.mark_dirty(None);
+    }
+
+    pub fn get_palette(&self) -> Palette {
+        self.palette
+    }
+
+    //each display pixel is drawn as a scale x scale block in the rgba buffer
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.clamp(1, MAX_SCALE);
+        self.rgba = vec![0; self.get_rgba_width() * self.get_rgba_height() * BYTES_PER_RGBA_PIXEL];
+        self.mark_dirty(None);
+    }
+
+    pub fn get_scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn get_rgba_width(&self) -> usize {
+        COLUMNS * self.scale
+    }
+
+    pub fn get_rgba_height(&self) -> usize {
+        ROWS * self.scale
+    }
+
+    //passing None switches the phosphor filter off, so the display shows the raw 1-bit memory again
+    pub fn set_phosphor_mode(&mut self, mode: Option<PhosphorMode>) {
+        self.phosphor = mode.map(Phosphor::new);
+        self.mark_dirty(None);
+    }
+
+    pub fn get_phosphor_mode(&self) -> Option<PhosphorMode> {
+        self.phosphor.as_ref().map(|phosphor| phosphor.get_mode())
+    }
+
+    //forgets the afterglow of everything drawn so far, which CLS keeps so that flickering sprites still show
+    pub fn clear_phosphor(&mut self) {
+        if let Some(phosphor) = self.phosphor.as_mut() {
+            phosphor.clear();
+            self.mark_dirty(None);
+        }
+    }
+
+    //grayscale intensity of every pixel after the phosphor filter has been applied
+    pub fn get_intensity(&self) -> Option<&[u8]> {
+        self.phosphor.as_ref().map(|phosphor| phosphor.get_intensity())
+    }
+
+    //should be called once per 60Hz frame
+    pub fn end_frame(&mut self) {
+        let intensity_changed = match self.phosphor.as_mut() {
+            Some(phosphor) => phosphor.end_frame(&self.memory),
+            None => false,
+        };
+        if intensity_changed {
+            self.mark_dirty(None);
+        }
+    }
+
+    fn get_pixel_colour(&self, index: usize) -> Colour {
+        let pixel = self.memory[index];
+        match self.phosphor.as_ref() {
+            Some(phosphor) => {
+                let intensity = phosphor.get_intensity()[index] as u16;
+                let background = self.palette.get_background();
+                let foreground = self.palette.get_colour(pixel.max(1));
+                let blend = |bg: u8, fg: u8| (((bg as u16) * (FULL_INTENSITY as u16 - intensity) + (fg as u16) * intensity) / (FULL_INTENSITY as u16)) as u8;
+                Colour::new(blend(background.r, foreground.r), blend(background.g, foreground.g), blend(background.b, foreground.b))
+            }
+            None => self.palette.get_colour(pixel),
+        }
+    }
+
+    pub fn render_rgba(&mut self) -> &[u8] {
+        let row_length = self.get_rgba_width() * BYTES_PER_RGBA_PIXEL;
+        for y in 0..ROWS {
+            let row_start = y * self.scale * row_length;
+            for x in 0..COLUMNS {
+                let rgba = self.get_pixel_colour((COLUMNS * y) + x).to_rgba();
+                let pixel_start = row_start + (x * self.scale * BYTES_PER_RGBA_PIXEL);
+                for chunk in self.rgba[pixel_start..pixel_start + (self.scale * BYTES_PER_RGBA_PIXEL)].chunks_mut(BYTES_PER_RGBA_PIXEL) {
+                    chunk.copy_from_slice(&rgba);
+                }
+            }
+            //the remaining lines of a scaled pixel row are identical to the first
+            for line in 1..self.scale {
+                self.rgba.copy_within(row_start..row_start + row_length, row_start + (line * row_length));
+            }
+        }
+        &self.rgba
+    }
+
+    //encodes the display as a png, using the current palette and scale
+    pub fn render_png(&mut self) -> Vec<u8> {
+        let width = self.get_rgba_width();
+        let height = self.get_rgba_height();
+        png::encode_rgba(width, height, self.render_rgba())
+    }
+
     fn wrap(&self, value: usize, max_value: usize) -> usize {
         let mut wrapped_value: usize = value;
         while wrapped_value >= max_value {
-            wrapped_value = wrapped_value - max_value;
+            wrapped_value -= max_value;
         }
         wrapped_value
     }
 }
 
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
 impl fmt::Display for Display {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for j in 0..ROWS - 1 {
             for i in 0..COLUMNS - 1 {
                 write!(f, "{} ", self.memory[(COLUMNS * j) + i])?;
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -65,6 +269,7 @@ impl fmt::Display for Display {
 #[cfg(test)]
 mod display_tests {
     use super::*;
+    use crate::palette::{BuiltInPalette, Colour};
 
     #[test]
     fn display_wrap() {
@@ -90,7 +295,7 @@ mod display_tests {
         assert_eq!(disp.memory[(COLUMNS * 3) + 8], 1);
         assert_eq!(disp.memory[(COLUMNS * 4) + 8], 1);
         assert_eq!(disp.memory[(COLUMNS * 4) + 9], 1);
-        assert_eq!(pixel_erased, false);
+        assert!(!pixel_erased);
 
         // -- 2 -- //
         let data: Vec<u8> = vec![0xF0, 0xF0, 0xF0];
@@ -124,7 +329,7 @@ mod display_tests {
         assert_eq!(disp.memory[(COLUMNS * 4) + 7], 0);
         assert_eq!(disp.memory[(COLUMNS * 4) + 8], 1);
         assert_eq!(disp.memory[(COLUMNS * 4) + 9], 1);
-        assert_eq!(pixel_erased, false);
+        assert!(!pixel_erased);
 
         // -- 3 -- //
         let data: Vec<u8> = vec![0x01, 0x02, 0x03];
@@ -158,6 +363,147 @@ mod display_tests {
         assert_eq!(disp.memory[(COLUMNS * 4) + 7], 0);
         assert_eq!(disp.memory[(COLUMNS * 4) + 8], 0);
         assert_eq!(disp.memory[(COLUMNS * 4) + 9], 0);
-        assert_eq!(pixel_erased, true);
+        assert!(pixel_erased);
+    }
+
+    #[test]
+    fn display_render_rgba() {
+        let mut disp = Display::new();
+        disp.set_palette(Palette::new(BuiltInPalette::Green));
+        disp.memory[1] = 1;
+
+        let foreground = Colour::from_rgb(0x33FF66).to_rgba();
+        let background = Colour::from_rgb(0x001A00).to_rgba();
+        let rgba = disp.render_rgba();
+
+        assert_eq!(rgba.len(), DISPLAY_MEMORY_SIZE * BYTES_PER_RGBA_PIXEL);
+        assert_eq!(rgba[0..4], background);
+        assert_eq!(rgba[4..8], foreground);
+        assert_eq!(rgba[8..12], background);
+    }
+
+    #[test]
+    fn display_render_rgba_scaled() {
+        let mut disp = Display::new();
+        disp.set_scale(2);
+        disp.memory[COLUMNS + 1] = 1;
+
+        let foreground = [0xFF, 0xFF, 0xFF, 0xFF];
+        let row_length = disp.get_rgba_width() * BYTES_PER_RGBA_PIXEL;
+        let rgba = disp.render_rgba();
+
+        assert_eq!(rgba.len(), DISPLAY_MEMORY_SIZE * 4 * BYTES_PER_RGBA_PIXEL);
+        for line in 2..4 {
+            for column in 0..4 {
+                let pixel_start = (line * row_length) + (column * BYTES_PER_RGBA_PIXEL);
+                let expected = if column >= 2 { foreground } else { [0x00, 0x00, 0x00, 0xFF] };
+                assert_eq!(rgba[pixel_start..pixel_start + 4], expected);
+            }
+        }
+
+        //the buffer is never larger than the largest scale needs
+        disp.set_scale(usize::MAX);
+        assert_eq!(disp.get_scale(), MAX_SCALE);
+        assert_eq!(disp.render_rgba().len(), DISPLAY_MEMORY_SIZE * MAX_SCALE * MAX_SCALE * BYTES_PER_RGBA_PIXEL);
+    }
+
+    #[test]
+    fn display_draw_clipped() {
+        let mut disp = Display::new();
+
+        //the bottom right corner, so the sprite would wrap onto all four corners of the screen
+        let location = (COLUMNS - 4, ROWS - 1);
+        disp.draw(&[0xFF, 0xFF], location);
+        assert_eq!(disp.memory.iter().filter(|pixel| **pixel == 1).count(), 16);
+        assert_eq!(disp.memory[0], 1);
+
+        disp.clear();
+        disp.draw_clipped(&[0xFF, 0xFF], location);
+        assert_eq!(disp.memory.iter().filter(|pixel| **pixel == 1).count(), 4);
+        assert_eq!(disp.memory[DISPLAY_MEMORY_SIZE - 1], 1);
+        assert_eq!(disp.memory[0], 0);
+
+        //the starting location still wraps
+        disp.clear();
+        disp.draw_clipped(&[0x80], (COLUMNS + 1, ROWS + 2));
+        assert_eq!(disp.memory[(COLUMNS * 2) + 1], 1);
+    }
+
+    #[test]
+    fn display_phosphor_fed_by_draw() {
+        let mut disp = Display::new();
+        disp.set_phosphor_mode(Some(PhosphorMode::Decay(0x00)));
+
+        //draw and erase a sprite within a single frame, as games do when moving sprites
+        disp.draw(&[0x80], (0, 0));
+        disp.draw(&[0x80], (0, 0));
+        assert_eq!(disp.memory[0], 0);
+
+        disp.end_frame();
+        assert_eq!(disp.get_intensity().unwrap()[0], FULL_INTENSITY);
+        assert_eq!(disp.render_rgba()[0..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        disp.end_frame();
+        assert_eq!(disp.get_intensity().unwrap()[0], 0);
+        assert_eq!(disp.render_rgba()[0..4], [0x00, 0x00, 0x00, 0xFF]);
+
+        //a reset forgets the afterglow
+        disp.set_phosphor_mode(Some(PhosphorMode::Decay(0x80)));
+        disp.draw(&[0x80], (0, 0));
+        disp.end_frame();
+        disp.clear_phosphor();
+        assert_eq!(disp.get_intensity().unwrap()[0], 0);
+
+        disp.set_phosphor_mode(None);
+        assert!(disp.get_intensity().is_none());
+    }
+
+    #[test]
+    fn display_dirty_region() {
+        let mut disp = Display::new();
+        assert_eq!(disp.take_dirty_region(), Some(DirtyRegion::full_screen()));
+        assert_eq!(disp.take_dirty_region(), None);
+
+        disp.draw(&[0xC0, 0x40], (10, 5));
+        assert_eq!(disp.take_dirty_region(), Some(DirtyRegion { x: 10, y: 5, width: 2, height: 2 }));
+
+        //drawing an empty sprite doesn't change any pixels
+        disp.draw(&[0x00], (20, 20));
+        assert!(!disp.is_dirty());
+
+        disp.draw(&[0x80], (30, 1));
+        disp.draw(&[0x80], (12, 6));
+        assert_eq!(
+            disp.take_dirty_region(),
+            Some(DirtyRegion {
+                x: 12,
+                y: 1,
+                width: 19,
+                height: 6
+            })
+        );
+
+        disp.clear();
+        assert_eq!(disp.take_dirty_region(), Some(DirtyRegion::full_screen()));
+        disp.clear();
+        assert!(!disp.is_dirty());
+    }
+
+    #[test]
+    fn display_zero_bits_over_lit_pixels() {
+        let mut disp = Display::new();
+        disp.draw(&[0xFF], (0, 0));
+        disp.take_dirty_region();
+
+        //only the first bit is set, so only the first pixel is erased
+        assert!(disp.draw(&[0x80], (0, 0)));
+        assert_eq!(disp.memory[0], 0);
+        assert!(disp.memory[1..8].iter().all(|pixel| *pixel == 1));
+        assert_eq!(disp.take_dirty_region(), Some(DirtyRegion { x: 0, y: 0, width: 1, height: 1 }));
+
+        //an empty sprite over lit pixels changes nothing
+        assert!(!disp.draw(&[0x00], (0, 0)));
+        assert!(disp.memory[1..8].iter().all(|pixel| *pixel == 1));
+        assert!(!disp.is_dirty());
     }
 }
@@ -0,0 +1,124 @@
+// The fonts that FX29 and FX30 point I at. Every interpreter shipped its own hex font, and some ROMs
+// draw parts of the font as graphics or read it as data, so they only look right with the font
+// they were written against.
+
+use super::memory::FONT_CHARACTERS;
+use wasm_bindgen::prelude::*;
+
+pub const FONT_CHARACTER_SIZE: usize = 5; //size in bytes
+pub const BIG_FONT_CHARACTER_SIZE: usize = 10;
+pub const FONT_SIZE: usize = 16 * FONT_CHARACTER_SIZE;
+pub const BIG_FONT_SIZE: usize = 16 * BIG_FONT_CHARACTER_SIZE;
+
+pub const COSMAC_VIP_FONT: [u8; FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, //"0" Character
+    0x60, 0x20, 0x20, 0x20, 0x70, //"1" Character
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, //"2" Character
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, //"3" Character
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, //"4" Character
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, //"5" Character
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, //"6" Character
+    0xF0, 0x10, 0x10, 0x10, 0x10, //"7" Character
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, //"8" Character
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, //"9" Character
+    0xF0, 0x90, 0xF0, 0x90, 0x90, //"A" Character
+    0xF0, 0x50, 0x70, 0x50, 0xF0, //"B" Character
+    0xF0, 0x80, 0x80, 0x80, 0xF0, //"C" Character
+    0xF0, 0x50, 0x50, 0x50, 0xF0, //"D" Character
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, //"E" Character
+    0xF0, 0x80, 0xF0, 0x80, 0x80, //"F" Character
+];
+
+pub const DREAM_6800_FONT: [u8; FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, //"0" Character
+    0x40, 0x40, 0x40, 0x40, 0x40, //"1" Character
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, //"2" Character
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, //"3" Character
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, //"4" Character
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, //"5" Character
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, //"6" Character
+    0xE0, 0x20, 0x20, 0x20, 0x20, //"7" Character
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, //"8" Character
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, //"9" Character
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, //"A" Character
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, //"B" Character
+    0xE0, 0x80, 0x80, 0x80, 0xE0, //"C" Character
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, //"D" Character
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, //"E" Character
+    0xE0, 0x80, 0xC0, 0x80, 0x80, //"F" Character
+];
+
+pub const ETI_660_FONT: [u8; FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, //"0" Character
+    0x20, 0x20, 0x20, 0x20, 0x20, //"1" Character
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, //"2" Character
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, //"3" Character
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, //"4" Character
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, //"5" Character
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, //"6" Character
+    0xE0, 0x20, 0x20, 0x20, 0x20, //"7" Character
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, //"8" Character
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, //"9" Character
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, //"A" Character
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, //"B" Character
+    0xE0, 0x80, 0x80, 0x80, 0xE0, //"C" Character
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, //"D" Character
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, //"E" Character
+    0xE0, 0x80, 0xC0, 0x80, 0x80, //"F" Character
+];
+
+// SCHIP's 8x10 digits, which FX30 points at. SCHIP only had 0 to 9, and A to F are the ones XO-CHIP added.
+pub const BIG_FONT_CHARACTERS: [u8; BIG_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, //"0" Character
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, //"1" Character
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, //"2" Character
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, //"3" Character
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, //"4" Character
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, //"5" Character
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, //"6" Character
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, //"7" Character
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, //"8" Character
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, //"9" Character
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, //"A" Character
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, //"B" Character
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, //"C" Character
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, //"D" Character
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, //"E" Character
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, //"F" Character
+];
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontSet {
+    //the font this emulator has always used, which most modern interpreters share
+    Standard = 0,
+    CosmacVip = 1,
+    Dream6800 = 2,
+    Eti660 = 3,
+}
+
+pub const FONT_SETS: [FontSet; 4] = [FontSet::Standard, FontSet::CosmacVip, FontSet::Dream6800, FontSet::Eti660];
+
+impl FontSet {
+    pub fn get_characters(self) -> &'static [u8; FONT_SIZE] {
+        match self {
+            FontSet::Standard => &FONT_CHARACTERS,
+            FontSet::CosmacVip => &COSMAC_VIP_FONT,
+            FontSet::Dream6800 => &DREAM_6800_FONT,
+            FontSet::Eti660 => &ETI_660_FONT,
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            FontSet::Standard => "standard",
+            FontSet::CosmacVip => "vip",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::Eti660 => "eti660",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        FONT_SETS.iter().find(|font| font.get_name() == name).copied()
+    }
+}
@@ -0,0 +1,310 @@
+// Records frames from the display into an animated GIF. Like the PNG encoder, this is built into
+// the crate so that recordings don't need any external tools.
+
+use super::display::{Display, COLUMNS, DISPLAY_MEMORY_SIZE, MAX_SCALE, ROWS};
+use super::palette::{Palette, PALETTE_SIZE};
+use std::collections::HashMap;
+
+const FRAMES_PER_SECOND: usize = 60;
+const CENTISECONDS_PER_SECOND: usize = 100;
+const MIN_CODE_SIZE: u8 = 2; //enough for the four palette colours
+const MAX_CODE: u16 = 4095; //GIF codes are at most 12 bits long
+const MAX_SUB_BLOCK_SIZE: usize = 255;
+
+struct RecordedFrame {
+    pixels: [u8; DISPLAY_MEMORY_SIZE],
+    //how many 60Hz frames this image stays on screen for
+    duration: usize,
+}
+
+pub struct GifRecorder {
+    scale: usize,
+    palette: Palette,
+    deduplicate: bool,
+    frames: Vec<RecordedFrame>,
+}
+
+impl GifRecorder {
+    pub fn new(scale: usize, palette: Palette, deduplicate: bool) -> Self {
+        GifRecorder {
+            scale: scale.clamp(1, MAX_SCALE),
+            palette,
+            deduplicate,
+            frames: Vec::new(),
+        }
+    }
+
+    //should be called once per 60Hz frame
+    pub fn capture(&mut self, display: &Display) {
+        if self.deduplicate {
+            if let Some(previous) = self.frames.last_mut() {
+                if previous.pixels[..] == display.memory[..] {
+                    previous.duration += 1;
+                    return;
+                }
+            }
+        }
+        self.frames.push(RecordedFrame {
+            pixels: display.memory,
+            duration: 1,
+        });
+    }
+
+    //the number of images in the gif, which is less than the number of captured frames when deduplicating
+    pub fn get_image_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let width = (COLUMNS * self.scale) as u16;
+        let height = (ROWS * self.scale) as u16;
+
+        let mut gif = Vec::new();
+        gif.extend_from_slice(b"GIF89a");
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        //global colour table present, 8 bits per colour channel, 2^(1+1) colours in the table
+        gif.extend_from_slice(&[0xF1, 0, 0]);
+        for index in 0..PALETTE_SIZE {
+            let colour = self.palette.get_colour(index as u8);
+            gif.extend_from_slice(&[colour.r, colour.g, colour.b]);
+        }
+
+        //netscape application extension, which makes the animation loop forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        let mut elapsed_frames = 0;
+        for frame in self.frames.iter() {
+            //gif delays are in hundredths of a second, so round the start and end times of each
+            //frame rather than each delay, to stop the rounding errors from adding up
+            let start = (elapsed_frames * CENTISECONDS_PER_SECOND + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND;
+            elapsed_frames += frame.duration;
+            let end = (elapsed_frames * CENTISECONDS_PER_SECOND + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND;
+            let delay = (end - start).min(u16::MAX as usize) as u16;
+
+            //graphic control extension
+            gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+            gif.extend_from_slice(&delay.to_le_bytes());
+            gif.extend_from_slice(&[0x00, 0x00]);
+
+            //image descriptor, covering the whole canvas and using the global colour table
+            gif.push(0x2C);
+            gif.extend_from_slice(&[0, 0, 0, 0]);
+            gif.extend_from_slice(&width.to_le_bytes());
+            gif.extend_from_slice(&height.to_le_bytes());
+            gif.push(0x00);
+
+            gif.push(MIN_CODE_SIZE);
+            let data = lzw_compress(&self.scale_frame(&frame.pixels));
+            for block in data.chunks(MAX_SUB_BLOCK_SIZE) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0x00);
+        }
+
+        gif.push(0x3B);
+        gif
+    }
+
+    fn scale_frame(&self, pixels: &[u8; DISPLAY_MEMORY_SIZE]) -> Vec<u8> {
+        let mut scaled = Vec::with_capacity(DISPLAY_MEMORY_SIZE * self.scale * self.scale);
+        for row in pixels.chunks(COLUMNS) {
+            for _ in 0..self.scale {
+                for pixel in row {
+                    for _ in 0..self.scale {
+                        scaled.push(*pixel % (PALETTE_SIZE as u8));
+                    }
+                }
+            }
+        }
+        scaled
+    }
+}
+
+fn lzw_compress(indices: &[u8]) -> Vec<u8> {
+    let clear_code: u16 = 1 << MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter::new();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = (MIN_CODE_SIZE + 1) as u32;
+
+    writer.write(clear_code, code_size);
+    let mut current: Option<u16> = None;
+    for index in indices {
+        current = match current {
+            None => Some(*index as u16),
+            Some(prefix) => match table.get(&(prefix, *index)) {
+                Some(code) => Some(*code),
+                None => {
+                    writer.write(prefix, code_size);
+                    table.insert((prefix, *index), next_code);
+                    if next_code == (1 << code_size) && code_size < 12 {
+                        code_size += 1;
+                    }
+                    next_code += 1;
+                    if next_code > MAX_CODE {
+                        //the table is full, so start again from scratch
+                        writer.write(clear_code, code_size);
+                        table.clear();
+                        next_code = end_code + 1;
+                        code_size = (MIN_CODE_SIZE + 1) as u32;
+                    }
+                    Some(*index as u16)
+                }
+            },
+        };
+    }
+    if let Some(prefix) = current {
+        writer.write(prefix, code_size);
+    }
+    writer.write(end_code, code_size);
+    writer.finish()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            current: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn write(&mut self, code: u16, size: u32) {
+        self.current |= (code as u32) << self.bit_count;
+        self.bit_count += size;
+        while self.bit_count >= 8 {
+            self.bytes.push((self.current & 0xFF) as u8);
+            self.current >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod gif_tests {
+    use super::*;
+
+    // A straightforward LZW decoder, used to check that the encoder's output can be read back
+    fn lzw_decompress(data: &[u8]) -> Vec<u8> {
+        let clear_code: usize = 1 << MIN_CODE_SIZE;
+        let end_code = clear_code + 1;
+        let mut output = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = (MIN_CODE_SIZE + 1) as usize;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut bit_position = 0;
+
+        loop {
+            let mut code = 0;
+            for i in 0..code_size {
+                let bit = (data[(bit_position + i) / 8] >> ((bit_position + i) % 8)) & 1;
+                code |= (bit as usize) << i;
+            }
+            bit_position += code_size;
+
+            if code == clear_code {
+                table = (0..clear_code).map(|i| vec![i as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                code_size = (MIN_CODE_SIZE + 1) as usize;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return output;
+            }
+
+            let entry = match (code < table.len(), previous.as_ref()) {
+                (true, _) => table[code].clone(),
+                (false, Some(previous)) => {
+                    let mut entry = previous.clone();
+                    entry.push(previous[0]);
+                    entry
+                }
+                (false, None) => panic!("invalid code"),
+            };
+            output.extend_from_slice(&entry);
+            if let Some(mut previous) = previous {
+                previous.push(entry[0]);
+                table.push(previous);
+                if table.len() == (1 << code_size) && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn gif_lzw_round_trip() {
+        let mut seed: u32 = 1;
+        let indices: Vec<u8> = (0..20000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((seed >> 16) % 4) as u8
+            })
+            .collect();
+        assert_eq!(lzw_decompress(&lzw_compress(&indices)), indices);
+
+        let indices = vec![1; 5000];
+        assert_eq!(lzw_decompress(&lzw_compress(&indices)), indices);
+    }
+
+    #[test]
+    fn gif_deduplicate_frames() {
+        let mut display = Display::new();
+        let mut recorder = GifRecorder::new(1, Palette::default(), true);
+
+        recorder.capture(&display);
+        recorder.capture(&display);
+        display.memory[0] = 1;
+        recorder.capture(&display);
+        assert_eq!(recorder.get_image_count(), 2);
+        assert_eq!(recorder.frames[0].duration, 2);
+
+        let mut recorder = GifRecorder::new(1, Palette::default(), false);
+        recorder.capture(&display);
+        recorder.capture(&display);
+        assert_eq!(recorder.get_image_count(), 2);
+    }
+
+    #[test]
+    fn gif_frame_timing() {
+        let display = Display::new();
+        let mut recorder = GifRecorder::new(1, Palette::default(), false);
+        for _ in 0..3 {
+            recorder.capture(&display);
+        }
+        let gif = recorder.encode();
+
+        //at 60Hz, three frames last five hundredths of a second in total
+        let delays: Vec<u16> = gif
+            .windows(4)
+            .enumerate()
+            .filter(|(_, window)| window == &[0x21, 0xF9, 0x04, 0x00])
+            .map(|(position, _)| u16::from_le_bytes([gif[position + 4], gif[position + 5]]))
+            .collect();
+        assert_eq!(delays, vec![2, 1, 2]);
+        assert_eq!(&gif[0..6], b"GIF89a");
+        assert_eq!(gif[gif.len() - 1], 0x3B);
+    }
+}
@@ -0,0 +1,122 @@
+// Spots the loops ROMs sit in when they have nothing to do, so the front ends can stop spending
+// time on them. A ROM that jumps to itself has finished and will never do anything else. One that
+// waits for a key with FX0A, or spins reading the delay timer until it runs out, won't do anything
+// different until a key is pressed or the timers tick, so the rest of the frame can be skipped.
+
+use super::cpu::CPU;
+use super::disassembler::Instruction;
+use super::keyboard::Keyboard;
+use super::memory::Memory;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunState {
+    Running = 0,
+    //waiting for a key press or the delay timer, neither of which change until the next frame
+    Idle = 1,
+    //stuck in a jump to itself, which nothing can get it out of
+    Halted = 2,
+}
+
+// The state of the ROM at the instruction the CPU is about to execute
+pub fn get_run_state(memory: &Memory, cpu: &CPU, keyboard: &Keyboard) -> RunState {
+    let pc = cpu.get_pc();
+    let instruction = |offset: u16| memory.read_word((pc + offset) as usize).map(Instruction::decode).ok();
+
+    match instruction(0) {
+        Some(Instruction::Jp(address)) if address == pc => RunState::Halted,
+        Some(Instruction::LdKey(_)) if keyboard.get_key_pressed().is_none() => RunState::Idle,
+        //LD Vx, DT and a skip that loops back until the delay timer reaches kk. Either SE Vx, kk skips the
+        //jump back, or SNE Vx, kk skips a jump out to the jump back after it.
+        Some(Instruction::LdFromDelayTimer(x)) => {
+            let loop_end = match instruction(2) {
+                Some(Instruction::SeByte(y, kk)) if y == x => Some((kk, 4)),
+                Some(Instruction::SneByte(y, kk)) if y == x => Some((kk, 6)),
+                _ => None,
+            };
+            match loop_end {
+                Some((kk, offset)) if instruction(offset) == Some(Instruction::Jp(pc)) && cpu.get_delay_timer() != kk => RunState::Idle,
+                _ => RunState::Running,
+            }
+        }
+        _ => RunState::Running,
+    }
+}
+
+#[cfg(test)]
+mod idle_tests {
+    use super::*;
+    use crate::display::Display;
+    use crate::memory::PROGRAM_START;
+
+    fn load(memory: &mut Memory, program: &[u16]) {
+        let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes().to_vec()).collect();
+        memory.load_program(&bytes).unwrap();
+    }
+
+    #[test]
+    fn idle_self_jump() {
+        let mut memory = Memory::new();
+        let cpu = CPU::new();
+        let keyboard = Keyboard::new();
+        load(&mut memory, &[0x1200]);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Halted);
+        load(&mut memory, &[0x1202]);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Running);
+    }
+
+    #[test]
+    fn idle_key_wait() {
+        let mut memory = Memory::new();
+        let cpu = CPU::new();
+        let mut keyboard = Keyboard::new();
+        load(&mut memory, &[0xF30A]);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Idle);
+        keyboard.press_key(0x5);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Running);
+    }
+
+    #[test]
+    fn idle_delay_timer_loop() {
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        //set the delay timer, then wait for it to run out
+        load(&mut memory, &[0x6002, 0xF015, 0xF107, 0x3100, 0x1204, 0x00E0]);
+        for _ in 0..2 {
+            let opcode = memory.read_word(cpu.get_pc() as usize).unwrap();
+            cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
+        }
+        assert_eq!(cpu.get_pc(), PROGRAM_START as u16 + 4);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Idle);
+
+        cpu.decrement_timers();
+        cpu.decrement_timers();
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Running);
+
+        //a skip that doesn't guard a jump back isn't a loop
+        load(&mut memory, &[0x6002, 0xF015, 0xF107, 0x3100, 0x1208]);
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Running);
+    }
+
+    #[test]
+    fn idle_delay_timer_loop_with_jump_out() {
+        let mut memory = Memory::new();
+        let mut cpu = CPU::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        //set the delay timer, then jump out of the loop once it runs out
+        load(&mut memory, &[0x6002, 0xF015, 0xF107, 0x4100, 0x120A, 0x1204, 0x00E0]);
+        for _ in 0..2 {
+            let opcode = memory.read_word(cpu.get_pc() as usize).unwrap();
+            cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard).unwrap();
+        }
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Idle);
+
+        cpu.decrement_timers();
+        cpu.decrement_timers();
+        assert_eq!(get_run_state(&memory, &cpu, &keyboard), RunState::Running);
+    }
+}
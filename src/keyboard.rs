@@ -19,3 +19,9 @@ impl Keyboard {
         self.key_pressed = None;
     }
 }
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard::new()
+    }
+}
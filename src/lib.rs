@@ -1,21 +1,81 @@
+pub mod analysis;
+pub mod cheats;
+pub mod conformance;
+pub mod coverage;
 pub mod cpu;
+pub mod disassembler;
 pub mod display;
+pub mod font;
+pub mod gif;
+pub mod idle;
+pub mod json;
 pub mod keyboard;
 pub mod memory;
+pub mod palette;
+pub mod phosphor;
+pub mod png;
+pub mod profiler;
+pub mod quirks;
+pub mod romdb;
+pub mod sha1;
+pub mod sprites;
 pub mod stack;
+pub mod symbols;
+pub mod trace;
+pub mod tracefile;
 
-use cpu::CPU;
-use display::Display;
+use analysis::Analysis;
+use cheats::{CheatError, CheatList, Location, MemorySearch, SearchFilter, Snapshot};
+use coverage::Coverage;
+use cpu::{CPUError, CPU};
+use disassembler::{Instruction, OpcodeClass};
+use display::{DirtyRegion, Display};
+use font::FontSet;
+use gif::GifRecorder;
+use idle::RunState;
 use keyboard::Keyboard;
-use memory::Memory;
+use memory::{AccessKind, FillPattern, Memory, MemoryError, MemoryFault, Protection, PROGRAM_START};
+use palette::{BuiltInPalette, Colour, Palette};
+use phosphor::PhosphorMode;
+use profiler::Profiler;
+use quirks::{QuirkPreset, Quirks};
+use romdb::{DatabaseError, RomDatabase, RomInfo};
+use sprites::{SpriteFormat, SpriteLayout, SpriteSheet};
+use symbols::{SymbolError, SymbolTable};
+use trace::{RegisterSnapshot, TraceEntry, TraceFilter, Tracer};
+use tracefile::TraceRecord;
 use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetKind {
+    Warm = 0,
+    Cold = 1,
+}
+
 #[wasm_bindgen]
 pub struct Chip8 {
     cpu: CPU,
     memory: Memory,
     display: Display,
     keyboard: Keyboard,
+    gif_recorder: Option<GifRecorder>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    //the last ROM that was loaded, which a cold reset loads again, and its SHA-1
+    rom: Vec<u8>,
+    rom_hash: String,
+    symbols: SymbolTable,
+    //I and the number of bytes drawn by the last DXYN
+    last_sprite: Option<(u16, usize)>,
+    search: Option<MemorySearch>,
+    cheats: CheatList,
+    rom_database: RomDatabase,
+    //what the database knows about the loaded ROM
+    rom_info: Option<RomInfo>,
+    //the memory access that stopped execute_cycle, which stays stopped until it's cleared
+    memory_fault: Option<MemoryFault>,
 }
 
 #[wasm_bindgen]
@@ -28,15 +88,68 @@ impl Chip8 {
             memory: Memory::new(),
             display: Display::new(),
             keyboard: Keyboard::new(),
+            gif_recorder: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            rom: Vec::new(),
+            rom_hash: sha1::sha1_hex(&[]),
+            symbols: SymbolTable::new(),
+            last_sprite: None,
+            search: None,
+            cheats: CheatList::new(&sha1::sha1_hex(&[])),
+            rom_database: RomDatabase::embedded(),
+            rom_info: None,
+            memory_fault: None,
         }
     }
 
     // TODO: Fix error handling
     // Unwrapping for now, 'til I can work out how to pass custom errors through the wasm boundry
     pub fn execute_cycle(&mut self) {
-        let pc = self.cpu.get_pc() as usize;
-        let opcode = self.memory.read_word(pc).unwrap();
-        self.cpu.process_opcode(opcode, &mut self.display, &mut self.memory, &self.keyboard).unwrap();
+        if self.memory_fault.is_some() {
+            return;
+        }
+        match self.step() {
+            Err(CPUError::MemoryFault(fault)) => self.memory_fault = Some(fault),
+            result => result.unwrap(),
+        }
+    }
+
+    // Runs a frame with try_run_frame, stopping at a memory fault for the debugger
+    pub fn run_frame(&mut self, cycles: usize) -> RunState {
+        if self.memory_fault.is_some() {
+            self.decrement_timers();
+            return self.get_run_state();
+        }
+        match self.try_run_frame(cycles, |_, _, _| {}) {
+            Ok(state) => state,
+            Err(CPUError::MemoryFault(fault)) => {
+                self.memory_fault = Some(fault);
+                self.decrement_timers();
+                self.get_run_state()
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn get_run_state(&self) -> RunState {
+        idle::get_run_state(&self.memory, &self.cpu, &self.keyboard)
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        self.try_load_rom(rom).unwrap();
+    }
+
+    // Makes the random numbers generated by RND, and memory filled with random bytes, reproducible.
+    // The fill is used from the next cold reset or ROM load on.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.cpu.seed_rng(seed);
+        self.memory.seed_fill(seed);
+    }
+
+    pub fn set_quirk_preset(&mut self, preset: QuirkPreset) {
+        self.cpu.set_quirks(Quirks::new(preset));
     }
 
     pub fn get_display_width(&self) -> usize {
@@ -51,6 +164,101 @@ impl Chip8 {
         self.display.memory.as_ptr()
     }
 
+    pub fn clear_display(&mut self) {
+        self.display.clear();
+    }
+
+    pub fn is_display_dirty(&self) -> bool {
+        self.display.is_dirty()
+    }
+
+    // Returns the part of the display that changed since the last call, or undefined if nothing
+    // changed, in which case the frontend doesn't need to redraw at all
+    pub fn take_dirty_region(&mut self) -> Option<DirtyRegion> {
+        self.display.take_dirty_region()
+    }
+
+    pub fn set_palette(&mut self, palette: BuiltInPalette) {
+        self.display.set_palette(Palette::new(palette));
+    }
+
+    // Colours are passed as 0xRRGGBB
+    pub fn set_custom_palette(&mut self, foreground: u32, background: u32) {
+        self.display.set_palette(Palette::custom(Colour::from_rgb(foreground), Colour::from_rgb(background)));
+    }
+
+    pub fn set_four_colour_palette(&mut self, colour0: u32, colour1: u32, colour2: u32, colour3: u32) {
+        self.display.set_palette(Palette::four_colour([
+            Colour::from_rgb(colour0),
+            Colour::from_rgb(colour1),
+            Colour::from_rgb(colour2),
+            Colour::from_rgb(colour3),
+        ]));
+    }
+
+    pub fn set_display_scale(&mut self, scale: usize) {
+        self.display.set_scale(scale);
+    }
+
+    pub fn get_display_rgba_width(&self) -> usize {
+        self.display.get_rgba_width()
+    }
+
+    pub fn get_display_rgba_height(&self) -> usize {
+        self.display.get_rgba_height()
+    }
+
+    // Renders the display into its RGBA buffer, and returns a pointer to that buffer. The buffer
+    // is reallocated when the scale changes, so the pointer should not be held on to across frames.
+    pub fn render_display(&mut self) -> *const u8 {
+        self.display.render_rgba().as_ptr()
+    }
+
+    // Unlit pixels keep retain/255 of their brightness every frame
+    pub fn set_phosphor_decay(&mut self, retain: u8) {
+        self.display.set_phosphor_mode(Some(PhosphorMode::Decay(retain)));
+    }
+
+    // Each pixel's brightness is averaged over the last number of frames
+    pub fn set_phosphor_blend(&mut self, frames: usize) {
+        self.display.set_phosphor_mode(Some(PhosphorMode::Blend(frames)));
+    }
+
+    pub fn disable_phosphor(&mut self) {
+        self.display.set_phosphor_mode(None);
+    }
+
+    // Returns a null pointer if the phosphor filter is disabled
+    pub fn get_display_intensity(&self) -> *const u8 {
+        match self.display.get_intensity() {
+            Some(intensity) => intensity.as_ptr(),
+            None => std::ptr::null(),
+        }
+    }
+
+    // Returns the display as a png, using the current palette and scale
+    pub fn take_screenshot(&mut self) -> Vec<u8> {
+        self.display.render_png()
+    }
+
+    // Every frame from now on is captured, until stop_gif_recording is called. When deduplicating,
+    // identical consecutive frames are merged into a single, longer image.
+    pub fn start_gif_recording(&mut self, scale: usize, deduplicate: bool) {
+        self.gif_recorder = Some(GifRecorder::new(scale, self.display.get_palette(), deduplicate));
+    }
+
+    // Returns the animated gif, or an empty buffer if nothing was being recorded
+    pub fn stop_gif_recording(&mut self) -> Vec<u8> {
+        match self.gif_recorder.take() {
+            Some(recorder) => recorder.encode(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn is_recording_gif(&self) -> bool {
+        self.gif_recorder.is_some()
+    }
+
     pub fn get_memory_size(&self) -> usize {
         memory::MEMORY_SIZE
     }
@@ -75,33 +283,570 @@ impl Chip8 {
         self.cpu.get_sound_timer()
     }
 
-    pub fn clear_control_registers(&mut self) {
-        self.cpu.clear_pc();
-        self.cpu.clear_i();
-        self.cpu.clear_delay_timer();
-        self.cpu.clear_sound_timer();
+    // Restarts the loaded ROM with the CPU, stack, timers, display and keyboard as they power up.
+    // A warm reset leaves memory as the ROM left it, and a cold reset refills it and loads the font
+    // and the ROM again.
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.cpu.reset();
+        self.display.clear();
+        self.display.clear_phosphor();
+        self.keyboard.release_key();
+        self.last_sprite = None;
+        self.memory_fault = None;
+        if kind == ResetKind::Cold {
+            self.memory.clear();
+            self.memory.load_program(&self.rom).expect("the ROM fitted in memory when it was loaded");
+        }
+    }
+
+    // What memory is filled with from the next cold reset or ROM load on
+    pub fn set_ram_fill(&mut self, pattern: FillPattern) {
+        self.memory.set_fill(pattern);
     }
 
+    // The timers count down at 60Hz, so this is also where the display's frame ends and the
+    // cheats are applied
     pub fn decrement_timers(&mut self) {
         self.cpu.decrement_timers();
+        self.cheats.apply(&mut self.memory, &mut self.cpu);
+        self.display.end_frame();
+        if let Some(recorder) = self.gif_recorder.as_mut() {
+            recorder.capture(&self.display);
+        }
     }
 
     pub fn get_v_registers(&self) -> *const u8 {
         self.cpu.get_v_registers().as_ptr()
     }
 
+    // Every instruction executed from now on is recorded, keeping the most recent capacity entries
+    pub fn start_trace(&mut self, capacity: usize) {
+        self.tracer = Some(Tracer::new(capacity));
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    // Only instructions between start and end, inclusive, are recorded
+    pub fn set_trace_address_range(&mut self, start: u16, end: u16) {
+        self.update_trace_filter(|filter| filter.address_range = Some((start, end)));
+    }
+
+    // Once a class has been added, only instructions in the added classes are recorded
+    pub fn add_trace_class(&mut self, class: OpcodeClass) {
+        self.update_trace_filter(|filter| {
+            if !filter.classes.contains(&class) {
+                filter.classes.push(class);
+            }
+        });
+    }
+
+    pub fn clear_trace_filter(&mut self) {
+        self.update_trace_filter(|filter| *filter = TraceFilter::default());
+    }
+
+    pub fn get_trace_length(&self) -> usize {
+        self.tracer.as_ref().map_or(0, |tracer| tracer.get_entries().len())
+    }
+
+    // Returns the most recent count entries, one per line, oldest first
+    pub fn get_trace_text(&self, count: usize) -> String {
+        let entries = match self.tracer.as_ref() {
+            Some(tracer) => tracer.get_entries(),
+            None => return String::new(),
+        };
+        let skip = entries.len().saturating_sub(count);
+        entries
+            .iter()
+            .skip(skip)
+            .map(|entry| format!("{}\n", entry.to_string_with_symbols(&self.symbols)))
+            .collect()
+    }
+
+    pub fn clear_trace(&mut self) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.clear();
+        }
+    }
+
+    // Starts counting instructions from scratch
+    pub fn start_profiling(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn stop_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    // Returns the instruction counts per address, per class and per subroutine as a JSON object,
+    // or an empty string if the profiler isn't running
+    pub fn get_profile_json(&self) -> String {
+        self.profiler.as_ref().map_or(String::new(), |profiler| profiler.get_json())
+    }
+
+    // Starts recording which bytes of memory are executed, read and written
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn stop_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    pub fn is_recording_coverage(&self) -> bool {
+        self.coverage.is_some()
+    }
+
+    // Returns a pointer to one byte of coverage flags for every byte of memory, or a null pointer
+    // if coverage isn't being recorded
+    pub fn get_coverage(&self) -> *const u8 {
+        match self.coverage.as_ref() {
+            Some(coverage) => coverage.get_bitmap().as_ptr(),
+            None => std::ptr::null(),
+        }
+    }
+
+    // Returns the ROM as an annotated listing showing how each byte was used
+    pub fn get_coverage_listing(&self) -> String {
+        match self.coverage.as_ref() {
+            Some(coverage) => coverage.get_listing(&self.memory, PROGRAM_START, PROGRAM_START + self.rom.len()),
+            None => String::new(),
+        }
+    }
+
+    pub fn get_rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    // Returns a byte for every byte of the ROM, which is 1 if it's part of an instruction that can
+    // be reached from the entry point, and 0 if it's data
+    pub fn get_code_map(&self) -> Vec<u8> {
+        let analysis = self.analyse_rom();
+        (PROGRAM_START..PROGRAM_START + self.rom.len())
+            .map(|address| analysis.is_code(address as u16) as u8)
+            .collect()
+    }
+
+    // Returns the ROM's control flow graph in Graphviz's DOT format
+    pub fn get_control_flow_dot(&self) -> String {
+        self.analyse_rom().get_dot(&self.memory, &self.symbols)
+    }
+
+    // Returns a listing of the ROM with code and data separated
+    pub fn get_disassembly(&self) -> String {
+        self.analyse_rom().get_listing(&self.memory, PROGRAM_START, PROGRAM_START + self.rom.len(), &self.symbols)
+    }
+
+    // Loads a symbol file, replacing any symbols loaded before. Returns false, and keeps the old
+    // symbols, if the file couldn't be read.
+    pub fn load_symbols(&mut self, text: &str) -> bool {
+        self.try_load_symbols(text).is_ok()
+    }
+
+    pub fn clear_symbols(&mut self) {
+        self.symbols = SymbolTable::new();
+    }
+
+    pub fn get_label(&self, address: u16) -> Option<String> {
+        self.symbols.get_label(address).map(String::from)
+    }
+
+    // Returns a symbol file with a label for every subroutine in the ROM and its data regions
+    // marked, ready to be given better names
+    pub fn generate_symbols(&self) -> String {
+        let end = (PROGRAM_START + self.rom.len()) as u16;
+        SymbolTable::from_analysis(&self.analyse_rom(), PROGRAM_START as u16, end).to_string()
+    }
+
+    // The address of the sprite drawn by the last DXYN, if there has been one
+    pub fn get_last_sprite_address(&self) -> Option<u16> {
+        self.last_sprite.map(|(address, _)| address)
+    }
+
+    pub fn get_last_sprite_length(&self) -> usize {
+        self.last_sprite.map_or(0, |(_, length)| length)
+    }
+
+    // Draws length bytes of memory from start as sprites, in strips of rows bytes (SCHIP sprites
+    // are always 16x16) laid out columns to a line, with the sprite drawn last highlighted
+    pub fn render_sprites(&self, start: usize, length: usize, format: SpriteFormat, rows: usize, columns: usize, scale: usize) -> SpriteSheet {
+        let layout = SpriteLayout {
+            start,
+            length,
+            format,
+            rows,
+            columns,
+            scale,
+        };
+        self.get_sprite_sheet(&layout)
+    }
+
+    pub fn get_rom_hash(&self) -> String {
+        self.rom_hash.clone()
+    }
+
+    // Stops execute_cycle when an instruction accesses memory between start and end, inclusive, in a
+    // way the protection doesn't allow
+    pub fn protect_memory(&mut self, start: usize, end: usize, protection: Protection) {
+        self.memory.protect(start, end, protection);
+    }
+
+    // Makes the font and the rest of the space reserved for the interpreter read-only
+    pub fn protect_interpreter_area(&mut self) {
+        self.memory.protect(0, PROGRAM_START - 1, Protection::ReadOnly);
+    }
+
+    pub fn clear_memory_protection(&mut self) {
+        self.memory.clear_protection();
+    }
+
+    pub fn has_memory_fault(&self) -> bool {
+        self.memory_fault.is_some()
+    }
+
+    // Describes the access that stopped execute_cycle
+    pub fn get_memory_fault_text(&self) -> Option<String> {
+        self.memory_fault.map(|fault| fault.to_string())
+    }
+
+    // Lets execute_cycle carry on. The instruction that faulted is retried unless pc is changed.
+    pub fn clear_memory_fault(&mut self) {
+        self.memory_fault = None;
+    }
+
+    // Loads a different font at address, which has to leave room for the big font before the program.
+    // Anything else in the interpreter's area is cleared. Returns false if the font doesn't fit.
+    pub fn set_font(&mut self, font: FontSet, address: usize) -> bool {
+        self.try_set_font(font, address).is_ok()
+    }
+
+    pub fn get_font(&self) -> FontSet {
+        self.memory.get_font()
+    }
+
+    pub fn get_font_address(&self) -> usize {
+        self.memory.get_font_address()
+    }
+
+    // Adds the ROMs in a database in the chip-8-database's JSON format to the embedded one. The
+    // settings are applied the next time a ROM is loaded. Returns false if the JSON couldn't be read.
+    pub fn load_rom_database(&mut self, json: &str) -> bool {
+        self.try_load_rom_database(json).is_ok()
+    }
+
+    pub fn get_rom_title(&self) -> Option<String> {
+        self.rom_info.as_ref().map(|info| info.title.clone())
+    }
+
+    pub fn get_rom_authors(&self) -> Option<String> {
+        self.rom_info.as_ref().filter(|info| !info.authors.is_empty()).map(|info| info.authors.join(", "))
+    }
+
+    pub fn get_rom_platform(&self) -> Option<String> {
+        self.rom_info.as_ref().and_then(|info| info.platform.clone())
+    }
+
+    // How many instructions the ROM expects to run each frame, if the database knows
+    pub fn get_rom_tickrate(&self) -> Option<usize> {
+        self.rom_info.as_ref().and_then(|info| info.tickrate)
+    }
+
+    // The key for an action the database knows about, like up, down, left, right, a or b
+    pub fn get_rom_key(&self, action: &str) -> Option<u8> {
+        self.rom_info.as_ref().and_then(|info| info.get_key(action))
+    }
+
+    // Returns the actions the ROM has keys for, separated by spaces
+    pub fn get_rom_actions(&self) -> String {
+        match self.rom_info.as_ref() {
+            Some(info) => info.keys.iter().map(|(action, _)| action.as_str()).collect::<Vec<&str>>().join(" "),
+            None => String::new(),
+        }
+    }
+
+    // Takes a snapshot of memory and the V registers in which every byte is a candidate
+    pub fn start_memory_search(&mut self) {
+        self.search = Some(MemorySearch::new(Snapshot::new(&self.memory, &self.cpu)));
+    }
+
+    pub fn stop_memory_search(&mut self) {
+        self.search = None;
+    }
+
+    // Takes another snapshot, keeping the candidates that changed in the given way since the last
+    // one. Returns how many are left.
+    pub fn filter_memory_search(&mut self, filter: SearchFilter) -> usize {
+        let snapshot = Snapshot::new(&self.memory, &self.cpu);
+        match self.search.as_mut() {
+            Some(search) => {
+                search.filter(snapshot, filter);
+                search.get_candidates().len()
+            }
+            None => 0,
+        }
+    }
+
+    // Keeps the candidates that currently hold value. Returns how many are left.
+    pub fn filter_memory_search_value(&mut self, value: u8) -> usize {
+        let snapshot = Snapshot::new(&self.memory, &self.cpu);
+        match self.search.as_mut() {
+            Some(search) => {
+                search.filter_value(snapshot, value);
+                search.get_candidates().len()
+            }
+            None => 0,
+        }
+    }
+
+    // Returns up to count candidates, one per line, as the location and its value in the last snapshot
+    pub fn get_search_candidates(&self, count: usize) -> String {
+        match self.search.as_ref() {
+            Some(search) => search
+                .get_candidates()
+                .iter()
+                .take(count)
+                .map(|location| format!("{} {:02X}\n", location, search.get_value(*location)))
+                .collect(),
+            None => String::new(),
+        }
+    }
+
+    // Pins a location, V0 to VF or a hex address, to a value that is written back every frame.
+    // Returns false if the location couldn't be read.
+    pub fn pin_cheat(&mut self, location: &str, value: u8, name: &str) -> bool {
+        match Location::parse(location) {
+            Some(location) => {
+                self.cheats.pin(location, value, name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn unpin_cheat(&mut self, location: &str) -> bool {
+        Location::parse(location).is_some_and(|location| self.cheats.unpin(location))
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats = CheatList::new(&self.rom_hash);
+    }
+
+    // Returns the cheats in a form that load_cheats reads, tied to the loaded ROM
+    pub fn get_cheats(&self) -> String {
+        self.cheats.to_string()
+    }
+
+    // Replaces the cheats. Returns false, and keeps the old ones, if the file couldn't be read or
+    // is for a different ROM.
+    pub fn load_cheats(&mut self, text: &str) -> bool {
+        self.try_load_cheats(text).is_ok()
+    }
+
     pub fn press_key(&mut self, key: u8) {
         self.keyboard.press_key(key);
     }
 
     pub fn release_key(&mut self, key: u8) {
-        match self.keyboard.get_key_pressed() {
-            Some(currently_pressed) => {
-                if currently_pressed == key {
-                    self.keyboard.release_key();
-                }
+        if self.keyboard.get_key_pressed() == Some(key) {
+            self.keyboard.release_key();
+        }
+    }
+}
+
+// Methods that can't cross the wasm boundry, mostly because they return errors
+impl Chip8 {
+    // Runs up to cycles instructions, then the 60Hz timer tick, calling after_step with the pc and opcode
+    // of each instruction once it has run. The rest of the frame is skipped as soon as the ROM is idle
+    // or halted, since it wouldn't do anything different before the tick. Returns the state that ended
+    // the frame, or the error an instruction stopped with, in which case the timers don't tick.
+    pub fn try_run_frame<F>(&mut self, cycles: usize, mut after_step: F) -> Result<RunState, CPUError>
+    where
+        F: FnMut(&mut Chip8, u16, u16),
+    {
+        let mut state = self.get_run_state();
+        for _ in 0..cycles {
+            if state != RunState::Running {
+                break;
+            }
+            let pc = self.cpu.get_pc();
+            let opcode = self.read_opcode()?;
+            let result = self.step();
+            after_step(self, pc, opcode);
+            result?;
+            //the ROM only gets into one of the loops by jumping to it or waiting at FX0A, which both leave
+            //pc somewhere other than the next instruction, so the check can be skipped the rest of the time
+            let next_pc = self.cpu.get_pc();
+            if next_pc != pc.wrapping_add(2) && next_pc != pc.wrapping_add(4) {
+                state = self.get_run_state();
             }
-            None => {}
+        }
+        self.decrement_timers();
+        Ok(state)
+    }
+
+    pub fn step(&mut self) -> Result<(), CPUError> {
+        let pc = self.cpu.get_pc();
+        let opcode = self.read_opcode()?;
+        let traced = self.tracer.as_ref().is_some_and(|tracer| tracer.is_traced(pc, opcode));
+        let before = if traced { Some(RegisterSnapshot::new(&self.cpu)) } else { None };
+        let i = self.cpu.get_i();
+
+        let result = self.cpu.process_opcode(opcode, &mut self.display, &mut self.memory, &self.keyboard);
+
+        //errors are traced too, since the instruction that failed is usually the interesting one
+        if let (Some(before), Some(tracer)) = (before, self.tracer.as_mut()) {
+            let changes = before.get_changes(&RegisterSnapshot::new(&self.cpu));
+            tracer.record(TraceEntry { pc, opcode, changes });
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, opcode, self.cpu.get_stack().len());
+        }
+        //a DXYN held back by the display wait quirk leaves pc where it was, without drawing
+        let drew = result.is_ok() && self.cpu.get_pc() != pc;
+        if let (Some(coverage), true) = (self.coverage.as_mut(), result.is_ok()) {
+            coverage.record(pc, opcode, i, drew);
+        }
+        if let (Instruction::Drw(_, _, n), true) = (Instruction::decode(opcode), drew) {
+            self.last_sprite = Some((i, n as usize));
+        }
+        result
+    }
+
+    // The instruction that the next call to step will execute
+    pub fn read_opcode(&self) -> Result<u16, CPUError> {
+        let pc = self.cpu.get_pc();
+        self.memory
+            .fetch_word(pc as usize)
+            .map_err(|e| CPUError::MemoryFault(MemoryFault::new(pc, None, e, AccessKind::Execute)))
+    }
+
+    pub fn get_sprite_sheet(&self, layout: &SpriteLayout) -> SpriteSheet {
+        let highlight = self.last_sprite.map(|(address, length)| (address as usize, address as usize + length));
+        SpriteSheet::render(&self.memory, layout, &self.display.get_palette(), highlight)
+    }
+
+    pub fn try_load_rom(&mut self, rom: &[u8]) -> Result<(), MemoryError> {
+        self.memory.load_program(rom)?;
+        self.rom = rom.to_vec();
+        //a new ROM starts from a cold reset, with nothing left behind by the last one
+        self.reset(ResetKind::Cold);
+        self.search = None;
+        //cheats found in one ROM don't mean anything in another
+        let rom_hash = sha1::sha1_hex(rom);
+        if rom_hash != self.rom_hash {
+            self.cheats = CheatList::new(&rom_hash);
+            self.rom_hash = rom_hash;
+        }
+        //ROMs the database knows about are set up the way they need to be, overriding any quirks set before
+        self.rom_info = self.rom_database.get(&self.rom_hash).cloned();
+        if let Some(quirks) = self.rom_info.as_ref().and_then(|info| info.quirks) {
+            self.cpu.set_quirks(quirks);
+        }
+        Ok(())
+    }
+
+    pub fn try_set_font(&mut self, font: FontSet, address: usize) -> Result<(), MemoryError> {
+        self.memory.set_font(font, address)
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.cpu.get_quirks()
+    }
+
+    pub fn get_coverage_map(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn try_load_symbols(&mut self, text: &str) -> Result<(), SymbolError> {
+        self.symbols = SymbolTable::parse(text)?;
+        Ok(())
+    }
+
+    pub fn try_load_rom_database(&mut self, json: &str) -> Result<(), DatabaseError> {
+        self.rom_database.extend(RomDatabase::parse(json)?);
+        Ok(())
+    }
+
+    pub fn get_rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    pub fn try_load_cheats(&mut self, text: &str) -> Result<(), CheatError> {
+        let cheats = CheatList::parse(text)?;
+        if cheats.get_rom_hash() != self.rom_hash {
+            return Err(CheatError::DifferentRom);
+        }
+        self.cheats = cheats;
+        Ok(())
+    }
+
+    pub fn get_memory_fault(&self) -> Option<MemoryFault> {
+        self.memory_fault
+    }
+
+    pub fn get_cheat_list(&self) -> &CheatList {
+        &self.cheats
+    }
+
+    pub fn get_symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn analyse_rom(&self) -> Analysis {
+        Analysis::new(&self.memory, PROGRAM_START as u16)
+    }
+
+    pub fn get_cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    // Should be called once the instruction at pc has been executed
+    pub fn create_trace_record(&self, pc: u16, opcode: u16) -> TraceRecord {
+        TraceRecord::new(pc, opcode, &self.cpu, &self.memory, &self.display)
+    }
+
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn get_tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn get_tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn get_memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn get_display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn get_display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    fn update_trace_filter(&mut self, update: impl FnOnce(&mut TraceFilter)) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            let mut filter = tracer.get_filter().clone();
+            update(&mut filter);
+            tracer.set_filter(filter);
         }
     }
 }
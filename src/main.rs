@@ -0,0 +1,592 @@
+// A headless command line front end, mostly useful for taking screenshots and debugging ROMs
+// without a browser.
+
+use chip8::conformance;
+use chip8::disassembler::OpcodeClass;
+use chip8::font::FontSet;
+use chip8::idle::RunState;
+use chip8::memory::{FillPattern, Protection, DEFAULT_FONT_ADDRESS, MEMORY_SIZE, PROGRAM_START};
+use chip8::palette::BuiltInPalette;
+use chip8::quirks::{QuirkPreset, StackOverflow, QUIRK_PRESETS};
+use chip8::sprites::SpriteFormat;
+use chip8::stack::MAX_STACK_DEPTH;
+use chip8::trace::TraceFilter;
+use chip8::tracefile::{self, TraceRecord};
+use chip8::Chip8;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+const DEFAULT_FRAMES: usize = 600; //10 seconds at 60Hz
+const DEFAULT_CYCLES_PER_FRAME: usize = 8; //the same ~480Hz clock speed the web front end uses
+const DEFAULT_DIFF_CONTEXT: usize = 5;
+const PROFILE_HOTSPOTS: usize = 20;
+const SPRITE_ROWS: usize = 8;
+const SPRITE_COLUMNS: usize = 16;
+
+const USAGE: &str = "Usage: chip8 <rom> [options]
+       chip8 --conformance <directory>
+       chip8 --diff-traces <file> <file> [--context <n>]
+
+Options:
+    --frames <n>             Number of 60Hz frames to run for (default 600)
+    --cycles-per-frame <n>   Instructions executed every frame (default: the ROM database's tickrate, or 8)
+    --scale <n>              Integer scale used for image output, up to 32 (default 1)
+    --palette <name>         white, green, amber or octo (default white)
+    --quirks <preset>        modern, vip, schip or xo-chip (default: the ROM database's platform, or modern)
+    --stack-depth <n>        Number of nested CALLs allowed, up to 256 (default: 12 for vip, 32 for xo-chip, otherwise 16)
+    --stack-overflow <mode>  error, wrap or halt when CALL overflows the stack or RET underflows it (default error)
+    --stack-address <hex>    Keep the stack in memory from this address, as the COSMAC VIP did at EA0
+    --ram-fill <pattern>     What memory holds before the ROM writes to it: zero, ones or random (default zero)
+    --font <name>            standard, vip, dream6800 or eti660 (default standard)
+    --font-address <hex>     Address the font is loaded at, with the SCHIP big font after it (default 000)
+    --protect <from-to> <protection>
+                             Stop when an instruction breaks read-only, execute-only or no-access
+                             protection of the memory between two hex addresses, e.g. 000-1FF read-only
+    --rom-database <file>    Add the ROMs in a chip-8-database programs.json to the embedded database
+    --rom-info               Print what the ROM database knows about the ROM
+    --screenshot <file>      Write the display to a png once all frames have run
+    --gif <file>             Record the frames that were run into an animated gif
+    --gif-start <n>          Frame at which the gif recording starts (default 0)
+    --gif-frames <n>         Number of frames to record (default: until the end)
+    --gif-dedupe             Merge identical consecutive frames in the gif
+    --trace <file>           Write every instruction executed, and the registers it changed, to a file
+    --trace-range <from-to>  Only trace instructions between two hex addresses, e.g. 200-2FF
+    --trace-class <name>     Only trace display, flow, skip, load, arithmetic, memory, input, timer,
+                             random or invalid instructions. Can be given more than once.
+    --record-trace <file>    Write the full machine state after every instruction to a file
+    --profile <file>         Write a report of where the ROM spent its time
+    --profile-folded <file>  Write the time spent in each call stack, in the folded format flamegraph.pl reads
+    --coverage <file>        Write a listing of the ROM showing which bytes were executed, read and written
+    --sprites <file>         Write the ROM drawn as 8x8 sprites to a png, with the last sprite drawn in red
+    --cheats <file>          Load cheats saved from the web front end, pinning values every frame
+    --symbols <file>         Load labels and comments for the ROM, used by the listings and traces
+    --write-symbols <file>   Write a symbol file with generated labels for every subroutine in the ROM
+    --disassemble <file>     Write a listing of the ROM, following its control flow to separate code from data
+    --cfg <file>             Write the ROM's control flow graph in Graphviz's DOT format
+    --conformance <dir>      Run the test suite ROMs in a directory under every quirk preset
+    --diff-traces <a> <b>    Compare two files written by --record-trace and show where they diverge
+    --context <n>            Instructions shown before the divergence (default 5)";
+
+struct Options {
+    rom_path: String,
+    frames: usize,
+    cycles_per_frame: Option<usize>,
+    scale: usize,
+    palette: BuiltInPalette,
+    quirks: Option<QuirkPreset>,
+    rom_database_path: Option<String>,
+    stack_depth: Option<usize>,
+    stack_overflow: Option<StackOverflow>,
+    stack_address: Option<u16>,
+    ram_fill: FillPattern,
+    font: FontSet,
+    font_address: usize,
+    protected_regions: Vec<(u16, u16, Protection)>,
+    print_rom_info: bool,
+    screenshot_path: Option<String>,
+    gif_path: Option<String>,
+    gif_start: usize,
+    gif_frames: Option<usize>,
+    gif_dedupe: bool,
+    trace_path: Option<String>,
+    trace_filter: TraceFilter,
+    record_trace_path: Option<String>,
+    profile_path: Option<String>,
+    profile_folded_path: Option<String>,
+    coverage_path: Option<String>,
+    sprites_path: Option<String>,
+    cheats_path: Option<String>,
+    symbols_path: Option<String>,
+    write_symbols_path: Option<String>,
+    disassembly_path: Option<String>,
+    cfg_path: Option<String>,
+    conformance_path: Option<String>,
+    diff_trace_paths: Option<(String, String)>,
+    diff_context: usize,
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or(format!("{} expects a value", flag))?;
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+fn parse_palette(value: Option<String>) -> Result<BuiltInPalette, String> {
+    match value.as_deref() {
+        Some("white") => Ok(BuiltInPalette::White),
+        Some("green") => Ok(BuiltInPalette::Green),
+        Some("amber") => Ok(BuiltInPalette::Amber),
+        Some("octo") => Ok(BuiltInPalette::Octo),
+        Some(other) => Err(format!("Unknown palette '{}'", other)),
+        None => Err(String::from("--palette expects a value")),
+    }
+}
+
+fn parse_quirks(value: Option<String>) -> Result<QuirkPreset, String> {
+    let value = value.ok_or("--quirks expects a value")?;
+    QuirkPreset::from_name(&value).ok_or(format!("Unknown quirk preset '{}'", value))
+}
+
+fn parse_stack_overflow(value: Option<String>) -> Result<StackOverflow, String> {
+    let value = value.ok_or("--stack-overflow expects a value")?;
+    StackOverflow::from_name(&value).ok_or(format!("Unknown stack overflow mode '{}', expected error, wrap or halt", value))
+}
+
+fn parse_ram_fill(value: Option<String>) -> Result<FillPattern, String> {
+    let value = value.ok_or("--ram-fill expects a value")?;
+    FillPattern::from_name(&value).ok_or(format!("Unknown fill pattern '{}', expected zero, ones or random", value))
+}
+
+fn parse_font(value: Option<String>) -> Result<FontSet, String> {
+    let value = value.ok_or("--font expects a value")?;
+    FontSet::from_name(&value).ok_or(format!("Unknown font '{}', expected standard, vip, dream6800 or eti660", value))
+}
+
+fn parse_address(option: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or(format!("{} expects a value", option))?;
+    match usize::from_str_radix(&value, 16) {
+        Ok(address) if address < MEMORY_SIZE => Ok(address),
+        _ => Err(format!("{} expects a hex address like 050, got '{}'", option, value)),
+    }
+}
+
+fn parse_stack_depth(option: &str, value: Option<String>) -> Result<usize, String> {
+    match parse_number(option, value)? {
+        depth if (1..=MAX_STACK_DEPTH).contains(&depth) => Ok(depth),
+        depth => Err(format!("{} expects a depth from 1 to {}, got {}", option, MAX_STACK_DEPTH, depth)),
+    }
+}
+
+fn parse_stack_address(option: &str, value: Option<String>) -> Result<u16, String> {
+    let address = parse_address(option, value)?;
+    u16::try_from(address).map_err(|_| format!("{} expects a hex address below {:#05X}, got {:#X}", option, MEMORY_SIZE, address))
+}
+
+fn parse_address_range(option: &str, value: Option<String>) -> Result<(u16, u16), String> {
+    let value = value.ok_or(format!("{} expects a value", option))?;
+    let invalid = || format!("{} expects two hex addresses like 200-2FF, got '{}'", option, value);
+    let mut parts = value.splitn(2, '-');
+    let start = parts.next().and_then(|part| u16::from_str_radix(part, 16).ok()).ok_or_else(invalid)?;
+    let end = parts.next().and_then(|part| u16::from_str_radix(part, 16).ok()).ok_or_else(invalid)?;
+    Ok((start, end))
+}
+
+fn parse_protection(value: Option<String>) -> Result<Protection, String> {
+    let value = value.ok_or("--protect expects an address range and a protection")?;
+    Protection::from_name(&value).ok_or(format!("Unknown protection '{}', expected read-only, execute-only or no-access", value))
+}
+
+fn parse_opcode_class(value: Option<String>) -> Result<OpcodeClass, String> {
+    let value = value.ok_or("--trace-class expects a value")?;
+    OpcodeClass::from_name(&value).ok_or(format!("Unknown instruction class '{}'", value))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: DEFAULT_FRAMES,
+        cycles_per_frame: None,
+        scale: 1,
+        palette: BuiltInPalette::White,
+        quirks: None,
+        rom_database_path: None,
+        stack_depth: None,
+        stack_overflow: None,
+        stack_address: None,
+        ram_fill: FillPattern::Zero,
+        font: FontSet::Standard,
+        font_address: DEFAULT_FONT_ADDRESS,
+        protected_regions: Vec::new(),
+        print_rom_info: false,
+        screenshot_path: None,
+        gif_path: None,
+        gif_start: 0,
+        gif_frames: None,
+        gif_dedupe: false,
+        trace_path: None,
+        trace_filter: TraceFilter::default(),
+        record_trace_path: None,
+        profile_path: None,
+        profile_folded_path: None,
+        coverage_path: None,
+        sprites_path: None,
+        cheats_path: None,
+        symbols_path: None,
+        write_symbols_path: None,
+        disassembly_path: None,
+        cfg_path: None,
+        conformance_path: None,
+        diff_trace_paths: None,
+        diff_context: DEFAULT_DIFF_CONTEXT,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = parse_number(&arg, args.next())?,
+            "--cycles-per-frame" => options.cycles_per_frame = Some(parse_number(&arg, args.next())?),
+            "--scale" => options.scale = parse_number(&arg, args.next())?,
+            "--palette" => options.palette = parse_palette(args.next())?,
+            "--screenshot" => options.screenshot_path = Some(args.next().ok_or("--screenshot expects a file name")?),
+            "--gif" => options.gif_path = Some(args.next().ok_or("--gif expects a file name")?),
+            "--gif-start" => options.gif_start = parse_number(&arg, args.next())?,
+            "--gif-frames" => options.gif_frames = Some(parse_number(&arg, args.next())?),
+            "--gif-dedupe" => options.gif_dedupe = true,
+            "--trace" => options.trace_path = Some(args.next().ok_or("--trace expects a file name")?),
+            "--trace-range" => options.trace_filter.address_range = Some(parse_address_range(&arg, args.next())?),
+            "--stack-depth" => options.stack_depth = Some(parse_stack_depth(&arg, args.next())?),
+            "--stack-overflow" => options.stack_overflow = Some(parse_stack_overflow(args.next())?),
+            "--stack-address" => options.stack_address = Some(parse_stack_address(&arg, args.next())?),
+            "--ram-fill" => options.ram_fill = parse_ram_fill(args.next())?,
+            "--font" => options.font = parse_font(args.next())?,
+            "--font-address" => options.font_address = parse_address(&arg, args.next())?,
+            "--protect" => {
+                let (start, end) = parse_address_range(&arg, args.next())?;
+                options.protected_regions.push((start, end, parse_protection(args.next())?));
+            }
+            "--trace-class" => options.trace_filter.classes.push(parse_opcode_class(args.next())?),
+            "--quirks" => options.quirks = Some(parse_quirks(args.next())?),
+            "--rom-database" => options.rom_database_path = Some(args.next().ok_or("--rom-database expects a file name")?),
+            "--rom-info" => options.print_rom_info = true,
+            "--record-trace" => options.record_trace_path = Some(args.next().ok_or("--record-trace expects a file name")?),
+            "--profile" => options.profile_path = Some(args.next().ok_or("--profile expects a file name")?),
+            "--profile-folded" => options.profile_folded_path = Some(args.next().ok_or("--profile-folded expects a file name")?),
+            "--coverage" => options.coverage_path = Some(args.next().ok_or("--coverage expects a file name")?),
+            "--sprites" => options.sprites_path = Some(args.next().ok_or("--sprites expects a file name")?),
+            "--cheats" => options.cheats_path = Some(args.next().ok_or("--cheats expects a file name")?),
+            "--symbols" => options.symbols_path = Some(args.next().ok_or("--symbols expects a file name")?),
+            "--write-symbols" => options.write_symbols_path = Some(args.next().ok_or("--write-symbols expects a file name")?),
+            "--disassemble" => options.disassembly_path = Some(args.next().ok_or("--disassemble expects a file name")?),
+            "--cfg" => options.cfg_path = Some(args.next().ok_or("--cfg expects a file name")?),
+            "--conformance" => options.conformance_path = Some(args.next().ok_or("--conformance expects a directory")?),
+            "--diff-traces" => {
+                let a = args.next().ok_or("--diff-traces expects two file names")?;
+                let b = args.next().ok_or("--diff-traces expects two file names")?;
+                options.diff_trace_paths = Some((a, b));
+            }
+            "--context" => options.diff_context = parse_number(&arg, args.next())?,
+            "-h" | "--help" => return Err(String::from(USAGE)),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'\n\n{}", arg, USAGE)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    if options.conformance_path.is_none() && options.diff_trace_paths.is_none() {
+        options.rom_path = rom_path.ok_or(USAGE)?;
+    }
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    if let Some(path) = options.conformance_path {
+        return run_conformance(&path);
+    }
+    if let Some((a, b)) = options.diff_trace_paths {
+        return diff_traces(&a, &b, options.diff_context);
+    }
+
+    let rom = fs::read(&options.rom_path).map_err(|e| format!("Could not read {}: {}", options.rom_path, e))?;
+
+    let mut chip8 = Chip8::power_up();
+    if let Some(path) = &options.rom_database_path {
+        let json = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        chip8.try_load_rom_database(&json).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    chip8.set_ram_fill(options.ram_fill);
+    //loading the ROM applies the database's settings for it, so anything given on the command line goes after
+    chip8.try_load_rom(&rom).map_err(|e| e.to_string())?;
+    chip8.set_palette(options.palette);
+    chip8.set_display_scale(options.scale);
+    if let Some(preset) = options.quirks {
+        chip8.set_quirk_preset(preset);
+    }
+    let mut quirks = chip8.get_quirks();
+    quirks.stack_depth = options.stack_depth.unwrap_or(quirks.stack_depth);
+    quirks.stack_overflow = options.stack_overflow.unwrap_or(quirks.stack_overflow);
+    quirks.stack_address = options.stack_address.or(quirks.stack_address);
+    if let Some(stack_address) = quirks.stack_address {
+        if stack_address as usize + quirks.stack_depth * 2 > MEMORY_SIZE {
+            return Err(format!(
+                "A stack of {} entries doesn't fit between {:#05X} and the end of memory",
+                quirks.stack_depth, stack_address
+            ));
+        }
+    }
+    chip8.set_quirks(quirks);
+    chip8
+        .try_set_font(options.font, options.font_address)
+        .map_err(|_| format!("The font doesn't fit between {:#05X} and the program", options.font_address))?;
+    for (start, end, protection) in options.protected_regions.iter() {
+        chip8.protect_memory(*start as usize, *end as usize, *protection);
+    }
+    let cycles_per_frame = options.cycles_per_frame.or_else(|| chip8.get_rom_tickrate()).unwrap_or(DEFAULT_CYCLES_PER_FRAME);
+    if options.print_rom_info {
+        print_rom_info(&chip8);
+    }
+
+    let mut trace_file = match &options.trace_path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Could not write {}: {}", path, e))?;
+            //entries are written out after every instruction, so the buffer only ever holds one
+            chip8.start_trace(1);
+            if let Some(tracer) = chip8.get_tracer_mut() {
+                tracer.set_filter(options.trace_filter.clone());
+            }
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+    if let Some(path) = &options.cheats_path {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        chip8.try_load_cheats(&text).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = &options.symbols_path {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        chip8.try_load_symbols(&text).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    //these only look at the ROM, so they are written before it starts changing memory
+    if let Some(path) = &options.write_symbols_path {
+        fs::write(path, chip8.generate_symbols()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    if let Some(path) = &options.disassembly_path {
+        fs::write(path, chip8.get_disassembly()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    if let Some(path) = &options.cfg_path {
+        fs::write(path, chip8.get_control_flow_dot()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+
+    if options.profile_path.is_some() || options.profile_folded_path.is_some() {
+        chip8.start_profiling();
+    }
+    if options.coverage_path.is_some() {
+        chip8.start_coverage();
+    }
+    let mut record_trace_file = match &options.record_trace_path {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(path).map_err(|e| format!("Could not write {}: {}", path, e))?);
+            writeln!(file, "{}", tracefile::HEADER).map_err(|e| format!("Could not write {}: {}", path, e))?;
+            Some(file)
+        }
+        None => None,
+    };
+
+    let result = run_frames(&mut chip8, &options, cycles_per_frame, &mut trace_file, &mut record_trace_file);
+    //the traces are flushed even when the run stopped with an error, since that's when they're wanted most
+    let flushed = [
+        flush_trace(&mut trace_file, &options.trace_path),
+        flush_trace(&mut record_trace_file, &options.record_trace_path),
+    ];
+    for error in flushed.iter().filter_map(|flush| flush.as_ref().err()) {
+        if result.is_err() {
+            eprintln!("{}", error);
+        } else {
+            return Err(error.clone());
+        }
+    }
+    result?;
+    //the requested range may run past the last frame, in which case the recording is still going
+    write_gif(&mut chip8, &options.gif_path)?;
+
+    if let Some(profiler) = chip8.get_profiler() {
+        if let Some(path) = &options.profile_path {
+            fs::write(path, profiler.get_report(PROFILE_HOTSPOTS)).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        }
+        if let Some(path) = &options.profile_folded_path {
+            fs::write(path, profiler.get_folded_stacks()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        }
+    }
+
+    if let Some(path) = &options.coverage_path {
+        fs::write(path, chip8.get_coverage_listing()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+
+    if let Some(path) = &options.sprites_path {
+        let sheet = chip8.render_sprites(PROGRAM_START, chip8.get_rom_size(), SpriteFormat::Chip8, SPRITE_ROWS, SPRITE_COLUMNS, options.scale);
+        fs::write(path, sheet.to_png()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+
+    if let Some(path) = options.screenshot_path {
+        fs::write(&path, chip8.take_screenshot()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+
+    Ok(())
+}
+
+fn run_conformance(directory: &str) -> Result<(), String> {
+    let mut found_any = false;
+    for rom in conformance::get_test_suite() {
+        let path = Path::new(directory).join(rom.file_name);
+        let program = match fs::read(&path) {
+            Ok(program) => program,
+            Err(_) => continue,
+        };
+        found_any = true;
+
+        print!("{:<16}", rom.name);
+        for preset in QUIRK_PRESETS.iter() {
+            print!("{:<10}", preset.get_name());
+        }
+        println!();
+
+        let results = conformance::run_all_presets(&rom, &program);
+        for (index, cell) in rom.cells.iter().enumerate() {
+            print!("  {:<14}", cell.name);
+            for (_, result) in results.iter() {
+                match result {
+                    Ok(outcomes) => print!("{:<10}", outcomes[index].outcome.to_string()),
+                    Err(_) => print!("{:<10}", "error"),
+                }
+            }
+            println!();
+        }
+        for (preset, result) in results.iter() {
+            if let Err(e) = result {
+                println!("  {} stopped under {}: {}", rom.name, preset.get_name(), e);
+            }
+        }
+        println!();
+    }
+
+    if found_any {
+        Ok(())
+    } else {
+        Err(format!("None of the test suite ROMs were found in {}", directory))
+    }
+}
+
+fn read_trace(path: &str) -> Result<Vec<TraceRecord>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    tracefile::parse_trace(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn diff_traces(path_a: &str, path_b: &str, context: usize) -> Result<(), String> {
+    let a = read_trace(path_a)?;
+    let b = read_trace(path_b)?;
+    let divergence = match tracefile::find_divergence(&a, &b) {
+        Some(divergence) => divergence,
+        None => {
+            println!("The traces are identical ({} instructions)", a.len());
+            return Ok(());
+        }
+    };
+
+    println!("The traces diverge at instruction {}", divergence.index);
+    for difference in divergence.differences.iter() {
+        println!("    {}", difference);
+    }
+    let start = divergence.index.saturating_sub(context);
+    for (path, records) in [(path_a, &a), (path_b, &b)].iter() {
+        println!();
+        println!("{}:", path);
+        for (index, record) in records.iter().enumerate().skip(start).take(divergence.index + 1 - start) {
+            let marker = if index == divergence.index { ">" } else { " " };
+            println!("{} {:>8}  {}", marker, index, record);
+        }
+        if records.len() <= divergence.index {
+            println!("> {:>8}  (end of trace)", records.len());
+        }
+    }
+    Ok(())
+}
+
+fn print_rom_info(chip8: &Chip8) {
+    println!("SHA-1:     {}", chip8.get_rom_hash());
+    let info = match chip8.get_rom_info() {
+        Some(info) => info,
+        None => {
+            println!("Not in the ROM database");
+            return;
+        }
+    };
+    println!("Title:     {}", info.title);
+    if !info.authors.is_empty() {
+        println!("Authors:   {}", info.authors.join(", "));
+    }
+    if let Some(platform) = &info.platform {
+        println!("Platform:  {}", platform);
+    }
+    if let Some(tickrate) = info.tickrate {
+        println!("Tickrate:  {}", tickrate);
+    }
+    for (action, key) in info.keys.iter() {
+        println!("Key:       {} = {:X}", action, key);
+    }
+}
+
+fn run_frames(
+    chip8: &mut Chip8,
+    options: &Options,
+    cycles_per_frame: usize,
+    trace_file: &mut Option<BufWriter<File>>,
+    record_trace_file: &mut Option<BufWriter<File>>,
+) -> Result<(), String> {
+    let gif_end = options.gif_frames.map_or(options.frames, |frames| options.gif_start + frames);
+    for frame in 0..options.frames {
+        if options.gif_path.is_some() && frame == options.gif_start {
+            chip8.start_gif_recording(options.scale, options.gif_dedupe);
+        }
+        //the first write that fails is reported once the frame has ended
+        let mut written = Ok(());
+        let state = chip8.try_run_frame(cycles_per_frame, |chip8, pc, opcode| {
+            if written.is_ok() {
+                written = write_trace(chip8, trace_file, &options.trace_path).and_then(|_| write_trace_record(chip8, pc, opcode, record_trace_file, &options.record_trace_path));
+            }
+        });
+        written?;
+        let state = state.map_err(|e| e.to_string())?;
+        if frame + 1 == gif_end {
+            write_gif(chip8, &options.gif_path)?;
+        }
+        //a halted ROM will never change anything again, so there's no point running the rest of the frames
+        if state == RunState::Halted {
+            println!("The ROM halted at {:#05X} after {} frames", chip8.get_pc(), frame + 1);
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn flush_trace(file: &mut Option<BufWriter<File>>, path: &Option<String>) -> Result<(), String> {
+    if let (Some(file), Some(path)) = (file, path) {
+        file.flush().map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn write_trace(chip8: &mut Chip8, file: &mut Option<BufWriter<File>>, path: &Option<String>) -> Result<(), String> {
+    if let (Some(file), Some(path)) = (file, path) {
+        let entries = chip8.get_tracer_mut().map_or(Vec::new(), |tracer| tracer.take_entries());
+        for entry in entries {
+            let line = entry.to_string_with_symbols(chip8.get_symbols());
+            writeln!(file, "{}", line).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_trace_record(chip8: &Chip8, pc: u16, opcode: u16, file: &mut Option<BufWriter<File>>, path: &Option<String>) -> Result<(), String> {
+    if let (Some(file), Some(path)) = (file, path) {
+        let record = chip8.create_trace_record(pc, opcode);
+        writeln!(file, "{}", record).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn write_gif(chip8: &mut Chip8, path: &Option<String>) -> Result<(), String> {
+    if let (Some(path), true) = (path, chip8.is_recording_gif()) {
+        fs::write(path, chip8.stop_gif_recording()).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(run);
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
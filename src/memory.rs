@@ -1,7 +1,14 @@
+use super::font::{FontSet, BIG_FONT_CHARACTERS, BIG_FONT_CHARACTER_SIZE, BIG_FONT_SIZE, FONT_CHARACTER_SIZE, FONT_SIZE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::error;
 use std::fmt;
+use wasm_bindgen::prelude::*;
 
 pub const MEMORY_SIZE: usize = 4096; //support 4k of memory
+pub const PROGRAM_START: usize = 0x200; //programs are loaded after the space reserved for the interpreter
+pub const ADDRESS_MASK: usize = MEMORY_SIZE - 1; //addresses are 12 bits wide
+pub const DEFAULT_FONT_ADDRESS: usize = 0;
 
 pub const FONT_CHARACTERS: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, //"0" Character
@@ -22,24 +29,178 @@ pub const FONT_CHARACTERS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, //"F" Character
 ];
 
-const FONT_CHARACTER_SIZE: usize = 5; //size in bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    //fetching an instruction
+    Execute,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "write"),
+            AccessKind::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+// What instructions are allowed to do to a region of memory. Nothing stops a ROM from doing
+// anything to memory on the original hardware, so these are for catching ROMs that overwrite the
+// font or run off into their data.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protection {
+    ReadOnly = 0,
+    ExecuteOnly = 1,
+    NoAccess = 2,
+}
+
+pub const PROTECTIONS: [Protection; 3] = [Protection::ReadOnly, Protection::ExecuteOnly, Protection::NoAccess];
+
+impl Protection {
+    pub fn allows(self, access: AccessKind) -> bool {
+        match self {
+            Protection::ReadOnly => access != AccessKind::Write,
+            Protection::ExecuteOnly => access == AccessKind::Execute,
+            Protection::NoAccess => false,
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Protection::ReadOnly => "read-only",
+            Protection::ExecuteOnly => "execute-only",
+            Protection::NoAccess => "no-access",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PROTECTIONS.iter().find(|protection| protection.get_name() == name).copied()
+    }
+}
+
+// What memory holds before anything is written to it. Nothing cleared memory on the original
+// hardware, so ROMs that read bytes they never wrote can behave differently depending on it.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillPattern {
+    Zero = 0,
+    Ones = 1,
+    Random = 2,
+}
+
+pub const FILL_PATTERNS: [FillPattern; 3] = [FillPattern::Zero, FillPattern::Ones, FillPattern::Random];
+
+impl FillPattern {
+    // The seed is only used by Random, which gives the same bytes every time for the same seed
+    pub fn fill(self, bytes: &mut [u8], seed: u64) {
+        match self {
+            FillPattern::Zero => bytes.iter_mut().for_each(|byte| *byte = 0),
+            FillPattern::Ones => bytes.iter_mut().for_each(|byte| *byte = 0xFF),
+            FillPattern::Random => StdRng::seed_from_u64(seed).fill(bytes),
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            FillPattern::Zero => "zero",
+            FillPattern::Ones => "ones",
+            FillPattern::Random => "random",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        FILL_PATTERNS.iter().find(|pattern| pattern.get_name() == name).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryRegion {
+    pub start: usize,
+    //inclusive
+    pub end: usize,
+    pub protection: Protection,
+}
 
 pub struct Memory {
     pub memory: [u8; MEMORY_SIZE],
+    //checked by every access an instruction makes, but not by anything writing to memory directly
+    regions: Vec<MemoryRegion>,
+    //what clear fills memory with
+    fill: FillPattern,
+    //seeds the random fill, so runs with the same seed start with the same memory
+    fill_seed: u64,
+    font: FontSet,
+    //where the small font starts, with the big font straight after it
+    font_address: usize,
 }
 
 impl Memory {
     pub fn new() -> Self {
-        let mut mem = Memory { memory: [0; MEMORY_SIZE] };
+        let mut mem = Memory {
+            memory: [0; MEMORY_SIZE],
+            regions: Vec::new(),
+            fill: FillPattern::Zero,
+            fill_seed: rand::random(),
+            font: FontSet::Standard,
+            font_address: DEFAULT_FONT_ADDRESS,
+        };
         mem.clear();
         mem
     }
 
     pub fn clear(&mut self) {
-        self.memory = [0; MEMORY_SIZE];
-        for i in 0..FONT_CHARACTERS.len() {
-            self.memory[i] = FONT_CHARACTERS[i];
+        self.fill.fill(&mut self.memory, self.fill_seed);
+        self.load_font();
+    }
+
+    // Used from the next clear on
+    pub fn set_fill(&mut self, fill: FillPattern) {
+        self.fill = fill;
+    }
+
+    pub fn get_fill(&self) -> FillPattern {
+        self.fill
+    }
+
+    // Used from the next clear on
+    pub fn seed_fill(&mut self, seed: u64) {
+        self.fill_seed = seed;
+    }
+
+    // Moves the font somewhere else in the interpreter's area, which both fonts have to fit in. Only the
+    // old font is cleared, since the rest of the area can hold things like the stack.
+    pub fn set_font(&mut self, font: FontSet, address: usize) -> Result<(), MemoryError> {
+        match address.checked_add(FONT_SIZE + BIG_FONT_SIZE) {
+            Some(end) if end <= PROGRAM_START => {}
+            _ => return Err(MemoryError::InvalidAddress(address)),
         }
+        //what clear put where the old font is now, which is the start of what it fills memory with
+        let mut cleared = [0; PROGRAM_START];
+        self.fill.fill(&mut cleared, self.fill_seed);
+        let old_font = self.font_address..self.font_address + FONT_SIZE + BIG_FONT_SIZE;
+        self.memory[old_font.clone()].copy_from_slice(&cleared[old_font]);
+        self.font = font;
+        self.font_address = address;
+        self.load_font();
+        Ok(())
+    }
+
+    pub fn get_font(&self) -> FontSet {
+        self.font
+    }
+
+    pub fn get_font_address(&self) -> usize {
+        self.font_address
+    }
+
+    fn load_font(&mut self) {
+        let big_font_address = self.font_address + FONT_SIZE;
+        self.memory[self.font_address..big_font_address].copy_from_slice(self.font.get_characters());
+        self.memory[big_font_address..big_font_address + BIG_FONT_CHARACTERS.len()].copy_from_slice(&BIG_FONT_CHARACTERS);
     }
 
     pub fn read_word(&self, index: usize) -> Result<u16, MemoryError> {
@@ -49,58 +210,191 @@ impl Memory {
         match (first_byte, second_byte) {
             (Some(b1), Some(b2)) => {
                 let data = ((*b1 as u16) << 8) | (*b2 as u16);
-                return Ok(data);
+                Ok(data)
             }
-            _ => return Err(MemoryError::InvalidAddress(index)),
+            (None, _) => Err(MemoryError::InvalidAddress(index)),
+            //the word starts at the last byte of memory, so it's the second byte that's missing
+            (Some(_), None) => Err(MemoryError::InvalidAddress(index + 1)),
         }
     }
 
+    // Reads the instruction at index, which both of its bytes have to allow
+    pub fn fetch_word(&self, index: usize) -> Result<u16, MemoryError> {
+        self.check_access(index, AccessKind::Execute)?;
+        self.check_access(index + 1, AccessKind::Execute)?;
+        self.read_word(index)
+    }
+
     pub fn read_byte(&self, index: usize) -> Result<u8, MemoryError> {
+        self.check_access(index, AccessKind::Read)?;
         let byte = self.memory.get(index);
 
         match byte {
             Some(byte) => Ok(*byte),
-            _ => return Err(MemoryError::InvalidAddress(index)),
+            _ => Err(MemoryError::InvalidAddress(index)),
         }
     }
 
     pub fn read_multiple_bytes(&self, index: usize, bytes: u8) -> Result<&[u8], MemoryError> {
         let from = index;
         let to = index + (bytes as usize);
-        if to >= MEMORY_SIZE {
-            return Err(MemoryError::InvalidAddress(from + to));
+        if to > MEMORY_SIZE {
+            return Err(MemoryError::InvalidAddress(from.max(MEMORY_SIZE)));
+        }
+        for address in from..to {
+            self.check_access(address, AccessKind::Read)?;
         }
         Ok(&self.memory[from..to])
     }
 
     pub fn write_byte(&mut self, index: usize, byte: u8) -> Result<(), MemoryError> {
-        if (index as usize) >= MEMORY_SIZE {
+        self.check_write(index)?;
+        self.memory[index] = byte;
+        Ok(())
+    }
+
+    // Whether write_byte would succeed, without writing anything
+    pub fn check_write(&self, index: usize) -> Result<(), MemoryError> {
+        if index >= MEMORY_SIZE {
             return Err(MemoryError::InvalidAddress(index));
         }
-        self.memory[index as usize] = byte;
+        self.check_access(index, AccessKind::Write)
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), MemoryError> {
+        let end = PROGRAM_START + program.len();
+        if end > MEMORY_SIZE {
+            return Err(MemoryError::ProgramTooLarge(program.len()));
+        }
+        self.memory[PROGRAM_START..end].copy_from_slice(program);
         Ok(())
     }
 
+    // Protects the addresses from start to end, inclusive. Where regions overlap, the one added
+    // last wins.
+    pub fn protect(&mut self, start: usize, end: usize, protection: Protection) {
+        self.regions.push(MemoryRegion { start, end, protection });
+    }
+
+    pub fn clear_protection(&mut self) {
+        self.regions.clear();
+    }
+
+    pub fn get_regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    pub fn check_access(&self, index: usize, access: AccessKind) -> Result<(), MemoryError> {
+        let region = self.regions.iter().rev().find(|region| index >= region.start && index <= region.end);
+        match region {
+            Some(region) if !region.protection.allows(access) => Err(MemoryError::ProtectionViolation(index, access)),
+            _ => Ok(()),
+        }
+    }
+
     pub fn get_location_of_font_character(&self, character: u8) -> usize {
-        (character as usize) * FONT_CHARACTER_SIZE
+        //only the low nibble selects a character, so every value points at the font
+        self.font_address + ((character & 0x0F) as usize) * FONT_CHARACTER_SIZE
+    }
+
+    pub fn get_location_of_big_font_character(&self, character: u8) -> usize {
+        self.font_address + FONT_SIZE + ((character & 0x0F) as usize) * BIG_FONT_CHARACTER_SIZE
     }
 }
 
-#[derive(Debug)]
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MemoryError {
     InvalidAddress(usize),
+    ProgramTooLarge(usize),
+    ProtectionViolation(usize, AccessKind),
+}
+
+impl MemoryError {
+    pub fn get_address(&self) -> Option<usize> {
+        match *self {
+            MemoryError::InvalidAddress(address) | MemoryError::ProtectionViolation(address, _) => Some(address),
+            MemoryError::ProgramTooLarge(_) => None,
+        }
+    }
 }
 
 impl fmt::Display for MemoryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             MemoryError::InvalidAddress(addr) => write!(f, "Tried to read from an invalid address in the memory: {:#04X}!", addr),
+            MemoryError::ProgramTooLarge(size) => write!(f, "Program of {} bytes does not fit into memory!", size),
+            MemoryError::ProtectionViolation(addr, access) => write!(f, "Tried to {} a protected address in the memory: {:#04X}!", access, addr),
         }
     }
 }
 
 impl error::Error for MemoryError {}
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultCause {
+    OutOfRange,
+    ProtectionViolation,
+}
+
+impl FaultCause {
+    pub fn get_description(self) -> &'static str {
+        match self {
+            FaultCause::OutOfRange => "past the end of memory",
+            FaultCause::ProtectionViolation => "protected",
+        }
+    }
+}
+
+// A memory access that an instruction wasn't allowed to make, either because the address is past the
+// end of memory or because it is in a protected region
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryFault {
+    pub pc: u16,
+    //None when the instruction itself couldn't be fetched
+    pub opcode: Option<u16>,
+    //the first address the access failed at, which isn't always the one it started at
+    pub address: usize,
+    pub access: AccessKind,
+    pub cause: FaultCause,
+}
+
+impl MemoryFault {
+    // The fault for an access by the instruction at pc that memory refused with error
+    pub fn new(pc: u16, opcode: Option<u16>, error: MemoryError, access: AccessKind) -> Self {
+        let cause = match error {
+            MemoryError::ProtectionViolation(_, _) => FaultCause::ProtectionViolation,
+            MemoryError::InvalidAddress(_) | MemoryError::ProgramTooLarge(_) => FaultCause::OutOfRange,
+        };
+        MemoryFault {
+            pc,
+            opcode,
+            address: error.get_address().unwrap_or(pc as usize),
+            access,
+            cause,
+        }
+    }
+}
+
+impl fmt::Display for MemoryFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = self.cause.get_description();
+        match self.opcode {
+            Some(opcode) => write!(
+                f,
+                "The instruction at {:#05X} ({:04X}) tried to {} {:#05X}, which is {}",
+                self.pc, opcode, self.access, self.address, reason
+            ),
+            None => write!(f, "Tried to {} {:#05X}, which is {}", self.access, self.address, reason),
+        }
+    }
+}
+
 #[cfg(test)]
 mod memory_tests {
     use super::*;
@@ -108,8 +402,8 @@ mod memory_tests {
     #[test]
     fn memory_read_word() {
         let mut mem = Memory::new();
-        mem.memory[0] = 1 as u8;
-        mem.memory[1] = 2 as u8;
+        mem.memory[0] = 1;
+        mem.memory[1] = 2;
         let data = mem.read_word(0).unwrap();
         assert_eq!(data, 0x102);
     }
@@ -117,14 +411,18 @@ mod memory_tests {
     #[test]
     fn memory_read_multiple_bytes() {
         let mut mem = Memory::new();
-        mem.memory[1] = 2 as u8;
-        mem.memory[2] = 3 as u8;
-        mem.memory[3] = 4 as u8;
-        mem.memory[4] = 5 as u8;
+        mem.memory[1] = 2;
+        mem.memory[2] = 3;
+        mem.memory[3] = 4;
+        mem.memory[4] = 5;
 
         let expected_result = vec![240, 2, 3, 4, 5];
         let actual_result = mem.read_multiple_bytes(0, 5).unwrap();
         assert!(expected_result.len() == actual_result.len() && expected_result == actual_result);
+
+        //the last bytes of memory can be read, but nothing past them
+        assert_eq!(mem.read_multiple_bytes(MEMORY_SIZE - 2, 2).unwrap().len(), 2);
+        assert!(mem.read_multiple_bytes(MEMORY_SIZE - 2, 3).is_err());
     }
 
     #[test]
@@ -137,4 +435,104 @@ mod memory_tests {
         assert_eq!(data1, 0x5);
         assert_eq!(data2, 0x6);
     }
+
+    #[test]
+    fn memory_load_program() {
+        let mut mem = Memory::new();
+        mem.load_program(&[0x12, 0x34]).unwrap();
+        assert_eq!(mem.read_word(PROGRAM_START).unwrap(), 0x1234);
+
+        let program = vec![0; MEMORY_SIZE - PROGRAM_START + 1];
+        assert!(mem.load_program(&program).is_err());
+    }
+
+    #[test]
+    fn memory_fault_cause() {
+        let mut mem = Memory::new();
+        mem.protect(0xFFE, 0xFFE, Protection::ReadOnly);
+        let fault = MemoryFault::new(0xFFF, None, mem.fetch_word(0xFFF).unwrap_err(), AccessKind::Execute);
+        assert_eq!((fault.address, fault.cause), (MEMORY_SIZE, FaultCause::OutOfRange));
+        assert_eq!(fault.to_string(), "Tried to execute 0x1000, which is past the end of memory");
+
+        let fault = MemoryFault::new(0x200, Some(0xFE55), mem.write_byte(0xFFE, 0).unwrap_err(), AccessKind::Write);
+        assert_eq!((fault.address, fault.cause), (0xFFE, FaultCause::ProtectionViolation));
+        assert_eq!(fault.to_string(), "The instruction at 0x200 (FE55) tried to write 0xFFE, which is protected");
+    }
+
+    #[test]
+    fn memory_protection() {
+        let mut mem = Memory::new();
+        mem.protect(0x000, 0x1FF, Protection::ReadOnly);
+        mem.protect(0x300, 0x3FF, Protection::NoAccess);
+        mem.protect(0x380, 0x381, Protection::ExecuteOnly);
+
+        assert_eq!(mem.read_byte(0x000), Ok(0xF0));
+        assert_eq!(mem.write_byte(0x1FF, 1), Err(MemoryError::ProtectionViolation(0x1FF, AccessKind::Write)));
+        assert_eq!(mem.write_byte(0x200, 1), Ok(()));
+        assert_eq!(mem.read_multiple_bytes(0x2FE, 3), Err(MemoryError::ProtectionViolation(0x300, AccessKind::Read)));
+        assert_eq!(mem.fetch_word(0x2FF), Err(MemoryError::ProtectionViolation(0x300, AccessKind::Execute)));
+        assert_eq!(mem.fetch_word(0x380), Ok(0));
+        assert_eq!(mem.read_byte(0x380), Err(MemoryError::ProtectionViolation(0x380, AccessKind::Read)));
+
+        mem.clear_protection();
+        assert_eq!(mem.write_byte(0x300, 1), Ok(()));
+        assert_eq!(mem.fetch_word(MEMORY_SIZE - 1), Err(MemoryError::InvalidAddress(MEMORY_SIZE)));
+        assert_eq!(mem.read_multiple_bytes(MEMORY_SIZE - 1, 2), Err(MemoryError::InvalidAddress(MEMORY_SIZE)));
+        assert_eq!(Protection::from_name("execute-only"), Some(Protection::ExecuteOnly));
+    }
+
+    #[test]
+    fn memory_set_font() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.get_location_of_font_character(0x1A), 50);
+        assert_eq!(mem.get_location_of_big_font_character(0x2), 100);
+
+        mem.set_font(FontSet::CosmacVip, 0x50).unwrap();
+        assert_eq!(mem.memory[0], 0);
+        assert_eq!(mem.get_location_of_font_character(0x1), 0x55);
+        assert_eq!(mem.read_multiple_bytes(0x55, 5).unwrap(), vec![0x60, 0x20, 0x20, 0x20, 0x70]);
+        assert_eq!(mem.get_location_of_big_font_character(0x0), 0xA0);
+        assert_eq!(mem.memory[0xA0], 0xFF);
+
+        //the big font would run into the program
+        assert_eq!(mem.set_font(FontSet::Standard, 0x170), Err(MemoryError::InvalidAddress(0x170)));
+        assert_eq!(mem.set_font(FontSet::Standard, usize::MAX), Err(MemoryError::InvalidAddress(usize::MAX)));
+        assert_eq!(mem.get_font_address(), 0x50);
+
+        //moving the font leaves the rest of the interpreter's area alone
+        mem.memory[0x1F0] = 0xAB;
+        mem.set_font(FontSet::Standard, 0x100).unwrap();
+        assert_eq!(mem.memory[0x1F0], 0xAB);
+        assert_eq!(mem.memory[0x55], 0);
+        assert_eq!(mem.memory[0x100], 0xF0);
+        mem.set_font(FontSet::CosmacVip, 0x50).unwrap();
+
+        mem.clear();
+        assert_eq!(mem.get_font(), FontSet::CosmacVip);
+        assert_eq!(mem.memory[0x55], 0x60);
+    }
+
+    #[test]
+    fn memory_fill() {
+        let mut mem = Memory::new();
+        mem.set_fill(FillPattern::Ones);
+        assert_eq!(mem.memory[PROGRAM_START], 0);
+        mem.clear();
+        assert!(mem.memory[PROGRAM_START..].iter().all(|byte| *byte == 0xFF));
+        assert_eq!(mem.memory[..5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(FillPattern::from_name("random"), Some(FillPattern::Random));
+    }
+
+    #[test]
+    fn memory_random_fill_seed() {
+        let cleared = |seed: u64| {
+            let mut mem = Memory::new();
+            mem.set_fill(FillPattern::Random);
+            mem.seed_fill(seed);
+            mem.clear();
+            mem.memory
+        };
+        assert_eq!(cleared(1)[..], cleared(1)[..]);
+        assert_ne!(cleared(1)[PROGRAM_START..], cleared(2)[PROGRAM_START..]);
+    }
 }
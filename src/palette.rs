@@ -0,0 +1,123 @@
+use wasm_bindgen::prelude::*;
+
+pub const PALETTE_SIZE: usize = 4; //one colour for each combination of the two display planes
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Colour {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Colour { r, g, b }
+    }
+
+    //takes a colour in the form 0xRRGGBB, which is the easiest form to pass through the wasm boundry
+    pub fn from_rgb(rgb: u32) -> Self {
+        Colour {
+            r: ((rgb >> 16) & 0xFF) as u8,
+            g: ((rgb >> 8) & 0xFF) as u8,
+            b: (rgb & 0xFF) as u8,
+        }
+    }
+
+    pub fn to_rgba(self) -> [u8; 4] {
+        [self.r, self.g, self.b, 0xFF]
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BuiltInPalette {
+    White = 0,
+    Green = 1,
+    Amber = 2,
+    Octo = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    //index 0 is the background, 1 the foreground, 2 and 3 are only used when a second display plane is active
+    colours: [Colour; PALETTE_SIZE],
+}
+
+impl Palette {
+    pub fn new(built_in: BuiltInPalette) -> Self {
+        match built_in {
+            BuiltInPalette::White => Palette::custom(Colour::new(0xFF, 0xFF, 0xFF), Colour::new(0x00, 0x00, 0x00)),
+            BuiltInPalette::Green => Palette::custom(Colour::new(0x33, 0xFF, 0x66), Colour::new(0x00, 0x1A, 0x00)),
+            BuiltInPalette::Amber => Palette::custom(Colour::new(0xFF, 0xB0, 0x00), Colour::new(0x1A, 0x0F, 0x00)),
+            BuiltInPalette::Octo => Palette::four_colour([
+                Colour::new(0x99, 0x66, 0x00),
+                Colour::new(0xFF, 0xCC, 0x00),
+                Colour::new(0xFF, 0x66, 0x00),
+                Colour::new(0x66, 0x22, 0x00),
+            ]),
+        }
+    }
+
+    //for two-colour palettes, the colour of pixels where both planes are lit is the foreground colour
+    pub fn custom(foreground: Colour, background: Colour) -> Self {
+        Palette {
+            colours: [background, foreground, foreground, foreground],
+        }
+    }
+
+    pub fn four_colour(colours: [Colour; PALETTE_SIZE]) -> Self {
+        Palette { colours }
+    }
+
+    pub fn get_colour(&self, pixel: u8) -> Colour {
+        self.colours[(pixel as usize) % PALETTE_SIZE]
+    }
+
+    pub fn get_background(&self) -> Colour {
+        self.colours[0]
+    }
+
+    pub fn get_foreground(&self) -> Colour {
+        self.colours[1]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new(BuiltInPalette::White)
+    }
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    #[test]
+    fn palette_colour_from_rgb() {
+        let colour = Colour::from_rgb(0x12AB34);
+        assert_eq!(colour, Colour::new(0x12, 0xAB, 0x34));
+        assert_eq!(colour.to_rgba(), [0x12, 0xAB, 0x34, 0xFF]);
+    }
+
+    #[test]
+    fn palette_custom_two_colour() {
+        let foreground = Colour::from_rgb(0xFFFFFF);
+        let background = Colour::from_rgb(0x000000);
+        let palette = Palette::custom(foreground, background);
+
+        assert_eq!(palette.get_colour(0), background);
+        assert_eq!(palette.get_colour(1), foreground);
+        assert_eq!(palette.get_colour(2), foreground);
+        assert_eq!(palette.get_colour(3), foreground);
+    }
+
+    #[test]
+    fn palette_four_colour() {
+        let palette = Palette::new(BuiltInPalette::Octo);
+
+        assert_eq!(palette.get_colour(0), Colour::from_rgb(0x996600));
+        assert_eq!(palette.get_colour(1), Colour::from_rgb(0xFFCC00));
+        assert_eq!(palette.get_colour(2), Colour::from_rgb(0xFF6600));
+        assert_eq!(palette.get_colour(3), Colour::from_rgb(0x662200));
+    }
+}
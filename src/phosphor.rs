@@ -0,0 +1,155 @@
+use super::display::{DISPLAY_MEMORY_SIZE, MAX_BLEND_FRAMES};
+
+pub const FULL_INTENSITY: u8 = 0xFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhosphorMode {
+    //every frame, unlit pixels keep retain/255 of their intensity, like a CRT phosphor fading out
+    Decay(u8),
+    //each pixel's intensity is the fraction of the last n frames in which it was lit
+    Blend(usize),
+}
+
+pub struct Phosphor {
+    mode: PhosphorMode,
+    intensity: [u8; DISPLAY_MEMORY_SIZE],
+    //pixels that were lit at any point during the current frame, even if they were erased again
+    lit_this_frame: [bool; DISPLAY_MEMORY_SIZE],
+    history: Vec<[bool; DISPLAY_MEMORY_SIZE]>,
+    history_position: usize,
+}
+
+impl Phosphor {
+    pub fn new(mode: PhosphorMode) -> Self {
+        let mode = match mode {
+            PhosphorMode::Blend(frames) => PhosphorMode::Blend(frames.clamp(1, MAX_BLEND_FRAMES)),
+            mode => mode,
+        };
+        let history_length = match mode {
+            PhosphorMode::Decay(_) => 0,
+            PhosphorMode::Blend(frames) => frames,
+        };
+        Phosphor {
+            mode,
+            intensity: [0; DISPLAY_MEMORY_SIZE],
+            lit_this_frame: [false; DISPLAY_MEMORY_SIZE],
+            history: vec![[false; DISPLAY_MEMORY_SIZE]; history_length],
+            history_position: 0,
+        }
+    }
+
+    pub fn get_mode(&self) -> PhosphorMode {
+        self.mode
+    }
+
+    //called by the display whenever a pixel is switched on
+    pub fn light_pixel(&mut self, index: usize) {
+        self.lit_this_frame[index] = true;
+    }
+
+    //returns whether the intensity of any pixel changed
+    pub fn end_frame(&mut self, memory: &[u8; DISPLAY_MEMORY_SIZE]) -> bool {
+        let previous_intensity = self.intensity;
+        for (lit, pixel) in self.lit_this_frame.iter_mut().zip(memory.iter()) {
+            *lit = *lit || *pixel != 0;
+        }
+
+        match self.mode {
+            PhosphorMode::Decay(retain) => {
+                for (intensity, lit) in self.intensity.iter_mut().zip(self.lit_this_frame.iter()) {
+                    *intensity = if *lit {
+                        FULL_INTENSITY
+                    } else {
+                        (((*intensity as u16) * (retain as u16)) / (FULL_INTENSITY as u16)) as u8
+                    };
+                }
+            }
+            PhosphorMode::Blend(_) => {
+                self.history[self.history_position] = self.lit_this_frame;
+                self.history_position = (self.history_position + 1) % self.history.len();
+                let frames = self.history.len();
+                for (index, intensity) in self.intensity.iter_mut().enumerate() {
+                    let lit_frames = self.history.iter().filter(|frame| frame[index]).count();
+                    *intensity = ((lit_frames * (FULL_INTENSITY as usize)) / frames) as u8;
+                }
+            }
+        }
+
+        self.lit_this_frame = [false; DISPLAY_MEMORY_SIZE];
+        self.intensity[..] != previous_intensity[..]
+    }
+
+    pub fn get_intensity(&self) -> &[u8] {
+        &self.intensity[..]
+    }
+
+    pub fn clear(&mut self) {
+        self.intensity = [0; DISPLAY_MEMORY_SIZE];
+        self.lit_this_frame = [false; DISPLAY_MEMORY_SIZE];
+        for frame in self.history.iter_mut() {
+            *frame = [false; DISPLAY_MEMORY_SIZE];
+        }
+    }
+}
+
+#[cfg(test)]
+mod phosphor_tests {
+    use super::*;
+
+    #[test]
+    fn phosphor_decay() {
+        let mut phosphor = Phosphor::new(PhosphorMode::Decay(0x80));
+        let mut memory = [0; DISPLAY_MEMORY_SIZE];
+
+        memory[0] = 1;
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[0], FULL_INTENSITY);
+
+        memory[0] = 0;
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[0], 0x80);
+
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[0], 0x40);
+    }
+
+    #[test]
+    fn phosphor_pixel_erased_within_frame() {
+        let mut phosphor = Phosphor::new(PhosphorMode::Decay(0x00));
+        let memory = [0; DISPLAY_MEMORY_SIZE];
+
+        //a sprite that was drawn and erased again before the end of the frame should still show up
+        phosphor.light_pixel(5);
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[5], FULL_INTENSITY);
+
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[5], 0);
+    }
+
+    #[test]
+    fn phosphor_blend() {
+        let mut phosphor = Phosphor::new(PhosphorMode::Blend(4));
+        let mut memory = [0; DISPLAY_MEMORY_SIZE];
+
+        memory[0] = 1;
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[0], 0x3F);
+
+        memory[0] = 0;
+        phosphor.end_frame(&memory);
+        memory[0] = 1;
+        phosphor.end_frame(&memory);
+        assert_eq!(phosphor.get_intensity()[0], 0x7F);
+
+        memory[0] = 0;
+        for _ in 0..4 {
+            phosphor.end_frame(&memory);
+        }
+        assert_eq!(phosphor.get_intensity()[0], 0);
+
+        let phosphor = Phosphor::new(PhosphorMode::Blend(usize::MAX));
+        assert_eq!(phosphor.get_mode(), PhosphorMode::Blend(MAX_BLEND_FRAMES));
+        assert_eq!(phosphor.history.len(), MAX_BLEND_FRAMES);
+    }
+}
@@ -0,0 +1,332 @@
+// A small PNG encoder, so that screenshots can be taken without pulling in any external tools.
+// Images are written as 8-bit RGBA, compressed with a fixed-Huffman deflate stream. That is not
+// as small as a full deflate implementation would manage, but CHIP-8 screens are mostly long runs
+// of identical pixels, which the LZ77 pass below handles well.
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const COLOUR_TYPE_RGBA: u8 = 6;
+const BIT_DEPTH: u8 = 8;
+const FILTER_NONE: u8 = 0;
+
+pub fn encode_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width * height * 4, "RGBA buffer does not match the image dimensions");
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&PNG_SIGNATURE);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[BIT_DEPTH, COLOUR_TYPE_RGBA, 0, 0, 0]); //no compression, filter or interlace options
+    write_chunk(&mut png, b"IHDR", &header);
+
+    //every scanline is prefixed with the filter type it uses
+    let mut scanlines = Vec::with_capacity((width * 4 + 1) * height);
+    for row in rgba.chunks(width * 4) {
+        scanlines.push(FILTER_NONE);
+        scanlines.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines));
+
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for byte in data {
+        a = (a + (*byte as u32)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    //zlib header: deflate with a 32k window, no preset dictionary
+    writer.bytes.extend_from_slice(&[0x78, 0x01]);
+
+    writer.write_bits(1, 1); //final block
+    writer.write_bits(1, 2); //fixed huffman codes
+    for token in lz77(data) {
+        match token {
+            Token::Literal(byte) => write_literal_length_code(&mut writer, byte as u16),
+            Token::Match(length, distance) => write_match(&mut writer, length, distance),
+        }
+    }
+    write_literal_length_code(&mut writer, 256); //end of block
+
+    let mut bytes = writer.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+enum Token {
+    Literal(u8),
+    Match(usize, usize),
+}
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+const MAX_CHAIN: usize = 32;
+
+fn hash(data: &[u8], position: usize) -> usize {
+    let value = ((data[position] as usize) << 16) | ((data[position + 1] as usize) << 8) | (data[position + 2] as usize);
+    (value.wrapping_mul(2_654_435_761)) & (HASH_SIZE - 1)
+}
+
+fn lz77(data: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if position + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, position)];
+            let mut chain = 0;
+            while candidate != usize::MAX && position - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let max_length = MAX_MATCH.min(data.len() - position);
+                let mut length = 0;
+                while length < max_length && data[candidate + length] == data[position + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            tokens.push(Token::Match(best_length, best_distance));
+            for p in position..position + best_length {
+                insert_hash(data, p, &mut head, &mut previous);
+            }
+            position += best_length;
+        } else {
+            tokens.push(Token::Literal(data[position]));
+            insert_hash(data, position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+    tokens
+}
+
+fn insert_hash(data: &[u8], position: usize, head: &mut [usize], previous: &mut [usize]) {
+    if position + MIN_MATCH <= data.len() {
+        let h = hash(data, position);
+        previous[position] = head[h];
+        head[h] = position;
+    }
+}
+
+//(base length, extra bits) for length codes 257..285
+const LENGTH_CODES: [(usize, u32); 29] = [
+    (3, 0),
+    (4, 0),
+    (5, 0),
+    (6, 0),
+    (7, 0),
+    (8, 0),
+    (9, 0),
+    (10, 0),
+    (11, 1),
+    (13, 1),
+    (15, 1),
+    (17, 1),
+    (19, 2),
+    (23, 2),
+    (27, 2),
+    (31, 2),
+    (35, 3),
+    (43, 3),
+    (51, 3),
+    (59, 3),
+    (67, 4),
+    (83, 4),
+    (99, 4),
+    (115, 4),
+    (131, 5),
+    (163, 5),
+    (195, 5),
+    (227, 5),
+    (258, 0),
+];
+
+//(base distance, extra bits) for distance codes 0..29
+const DISTANCE_CODES: [(usize, u32); 30] = [
+    (1, 0),
+    (2, 0),
+    (3, 0),
+    (4, 0),
+    (5, 1),
+    (7, 1),
+    (9, 2),
+    (13, 2),
+    (17, 3),
+    (25, 3),
+    (33, 4),
+    (49, 4),
+    (65, 5),
+    (97, 5),
+    (129, 6),
+    (193, 6),
+    (257, 7),
+    (385, 7),
+    (513, 8),
+    (769, 8),
+    (1025, 9),
+    (1537, 9),
+    (2049, 10),
+    (3073, 10),
+    (4097, 11),
+    (6145, 11),
+    (8193, 12),
+    (12289, 12),
+    (16385, 13),
+    (24577, 13),
+];
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_CODES.iter().rposition(|(base, _)| *base <= length).unwrap();
+    let (base, extra_bits) = LENGTH_CODES[code];
+    write_literal_length_code(writer, 257 + code as u16);
+    writer.write_bits((length - base) as u32, extra_bits);
+
+    let code = DISTANCE_CODES.iter().rposition(|(base, _)| *base <= distance).unwrap();
+    let (base, extra_bits) = DISTANCE_CODES[code];
+    //distance codes are always 5 bits long when using the fixed huffman codes
+    writer.write_huffman_code(code as u32, 5);
+    writer.write_bits((distance - base) as u32, extra_bits);
+}
+
+fn write_literal_length_code(writer: &mut BitWriter, value: u16) {
+    let value = value as u32;
+    match value {
+        0..=143 => writer.write_huffman_code(0x30 + value, 8),
+        144..=255 => writer.write_huffman_code(0x190 + (value - 144), 9),
+        256..=279 => writer.write_huffman_code(value - 256, 7),
+        _ => writer.write_huffman_code(0xC0 + (value - 280), 8),
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            current: 0,
+            bit_count: 0,
+        }
+    }
+
+    //plain values are packed starting at the least significant bit
+    fn write_bits(&mut self, value: u32, count: u32) {
+        for i in 0..count {
+            self.current |= ((value >> i) & 1) << self.bit_count;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.bit_count = 0;
+            }
+        }
+    }
+
+    //huffman codes are packed starting at the most significant bit
+    fn write_huffman_code(&mut self, code: u32, length: u32) {
+        for i in (0..length).rev() {
+            self.write_bits((code >> i) & 1, 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod png_tests {
+    use super::*;
+
+    #[test]
+    fn png_crc32() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn png_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn png_lz77_round_trip() {
+        let data: Vec<u8> = (0..2000).map(|i| if (i / 37) % 3 == 0 { 0xFF } else { (i % 7) as u8 }).collect();
+        let mut decoded: Vec<u8> = Vec::new();
+        for token in lz77(&data) {
+            match token {
+                Token::Literal(byte) => decoded.push(byte),
+                Token::Match(length, distance) => {
+                    assert!((MIN_MATCH..=MAX_MATCH).contains(&length) && distance <= WINDOW_SIZE);
+                    for _ in 0..length {
+                        decoded.push(decoded[decoded.len() - distance]);
+                    }
+                }
+            }
+        }
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn png_encode_rgba() {
+        let rgba = vec![0xFF; 2 * 2 * 4];
+        let png = encode_rgba(2, 2, &rgba);
+
+        assert_eq!(png[0..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], 2u32.to_be_bytes());
+        assert_eq!(png[20..24], 2u32.to_be_bytes());
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
@@ -0,0 +1,238 @@
+// Counts how often each instruction runs, to find where a ROM spends its time. Time is measured in
+// instructions executed, since that is what the frontends use to pace the CPU.
+//
+// Subroutines are tracked by watching the depth of the CPU's stack after every instruction, so a
+// CALL that fails, or a ROM that jumps out of a subroutine without returning, can't confuse it.
+
+use super::disassembler::{Instruction, OpcodeClass, OPCODE_CLASSES};
+use super::memory::MEMORY_SIZE;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    //instructions executed in this subroutine and everything it called
+    pub inclusive: u64,
+    //instructions executed in this subroutine itself
+    pub exclusive: u64,
+}
+
+pub struct Profiler {
+    total: u64,
+    address_counts: Vec<u64>,
+    class_counts: HashMap<OpcodeClass, u64>,
+    subroutines: HashMap<u16, SubroutineStats>,
+    //the entry address of every subroutine that hasn't returned yet, outermost first
+    call_stack: Vec<u16>,
+    folded_stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            total: 0,
+            address_counts: vec![0; MEMORY_SIZE],
+            class_counts: HashMap::new(),
+            subroutines: HashMap::new(),
+            call_stack: Vec::new(),
+            folded_stacks: HashMap::new(),
+        }
+    }
+
+    // Should be called after every instruction, with the depth of the stack once it has run
+    pub fn record(&mut self, pc: u16, opcode: u16, stack_depth: usize) {
+        let instruction = Instruction::decode(opcode);
+        self.total += 1;
+        self.address_counts[pc as usize % MEMORY_SIZE] += 1;
+        *self.class_counts.entry(instruction.get_class()).or_insert(0) += 1;
+        *self.folded_stacks.entry(self.call_stack.clone()).or_insert(0) += 1;
+
+        //recursive subroutines are only counted once towards their own inclusive time
+        for (depth, address) in self.call_stack.iter().enumerate() {
+            if !self.call_stack[..depth].contains(address) {
+                self.subroutines.entry(*address).or_default().inclusive += 1;
+            }
+        }
+        if let Some(address) = self.call_stack.last() {
+            self.subroutines.entry(*address).or_default().exclusive += 1;
+        }
+
+        self.call_stack.truncate(stack_depth);
+        if stack_depth > self.call_stack.len() {
+            //the stack can only grow by one entry at a time, and only through CALL
+            let address = match instruction {
+                Instruction::Call(address) => address,
+                _ => pc,
+            };
+            self.call_stack.push(address);
+            self.subroutines.entry(address).or_default().calls += 1;
+        }
+    }
+
+    pub fn get_total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn get_address_count(&self, address: u16) -> u64 {
+        self.address_counts[address as usize % MEMORY_SIZE]
+    }
+
+    pub fn get_class_count(&self, class: OpcodeClass) -> u64 {
+        self.class_counts.get(&class).copied().unwrap_or(0)
+    }
+
+    pub fn get_subroutine(&self, address: u16) -> Option<SubroutineStats> {
+        self.subroutines.get(&address).copied()
+    }
+
+    // Every address that was executed, most executed first
+    pub fn get_hotspots(&self) -> Vec<(u16, u64)> {
+        let mut hotspots: Vec<(u16, u64)> = self
+            .address_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(address, count)| (address as u16, *count))
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hotspots
+    }
+
+    // Every subroutine that was called, the one with the most inclusive time first
+    pub fn get_subroutines(&self) -> Vec<(u16, SubroutineStats)> {
+        let mut subroutines: Vec<(u16, SubroutineStats)> = self.subroutines.iter().map(|(address, stats)| (*address, *stats)).collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        subroutines
+    }
+
+    pub fn get_report(&self, hotspot_count: usize) -> String {
+        let mut report = format!("{} instructions executed\n\n", self.total);
+
+        report.push_str("Instruction classes\n");
+        for class in OPCODE_CLASSES.iter() {
+            let count = self.get_class_count(*class);
+            if count > 0 {
+                report.push_str(&format!("    {:<12}{:>12}{:>8.2}%\n", class.get_name(), count, self.get_percentage(count)));
+            }
+        }
+
+        report.push_str("\nHotspots\n");
+        for (address, count) in self.get_hotspots().into_iter().take(hotspot_count) {
+            report.push_str(&format!("    {:04X}{:>12}{:>8.2}%\n", address, count, self.get_percentage(count)));
+        }
+
+        report.push_str("\nSubroutines         calls   inclusive   exclusive\n");
+        for (address, stats) in self.get_subroutines() {
+            report.push_str(&format!("    {:04X}{:>16}{:>12}{:>12}\n", address, stats.calls, stats.inclusive, stats.exclusive));
+        }
+        report
+    }
+
+    // One line per call stack with the number of instructions run in it, which is the input
+    // flamegraph.pl and similar tools expect
+    pub fn get_folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .folded_stacks
+            .iter()
+            .map(|(stack, count)| {
+                let mut frames = vec![String::from("main")];
+                frames.extend(stack.iter().map(|address| format!("sub_{:03X}", address)));
+                format!("{} {}", frames.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    pub fn get_json(&self) -> String {
+        let classes: Vec<String> = OPCODE_CLASSES
+            .iter()
+            .map(|class| format!("\"{}\":{}", class.get_name(), self.get_class_count(*class)))
+            .collect();
+        let hotspots: Vec<String> = self
+            .get_hotspots()
+            .iter()
+            .map(|(address, count)| format!("{{\"address\":{},\"count\":{}}}", address, count))
+            .collect();
+        let subroutines: Vec<String> = self
+            .get_subroutines()
+            .iter()
+            .map(|(address, stats)| {
+                format!(
+                    "{{\"address\":{},\"calls\":{},\"inclusive\":{},\"exclusive\":{}}}",
+                    address, stats.calls, stats.inclusive, stats.exclusive
+                )
+            })
+            .collect();
+        format!(
+            "{{\"instructions\":{},\"classes\":{{{}}},\"hotspots\":[{}],\"subroutines\":[{}]}}",
+            self.total,
+            classes.join(","),
+            hotspots.join(","),
+            subroutines.join(",")
+        )
+    }
+
+    fn get_percentage(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+#[cfg(test)]
+mod profiler_tests {
+    use super::*;
+
+    // Runs main -> 0x300 -> 0x400 and back, with the stack depth each instruction leaves behind
+    fn profile_calls() -> Profiler {
+        let mut profiler = Profiler::new();
+        profiler.record(0x200, 0x6001, 0);
+        profiler.record(0x202, 0x2300, 1);
+        profiler.record(0x300, 0x7001, 1);
+        profiler.record(0x302, 0x2400, 2);
+        profiler.record(0x400, 0xD015, 2);
+        profiler.record(0x402, 0x00EE, 1);
+        profiler.record(0x304, 0x00EE, 0);
+        profiler.record(0x204, 0x1204, 0);
+        profiler.record(0x204, 0x1204, 0);
+        profiler
+    }
+
+    #[test]
+    fn profiler_counts() {
+        let profiler = profile_calls();
+        assert_eq!(profiler.get_total(), 9);
+        assert_eq!(profiler.get_address_count(0x204), 2);
+        assert_eq!(profiler.get_class_count(OpcodeClass::Flow), 6);
+        assert_eq!(profiler.get_class_count(OpcodeClass::Display), 1);
+        assert_eq!(profiler.get_hotspots()[0], (0x204, 2));
+    }
+
+    #[test]
+    fn profiler_subroutines() {
+        let profiler = profile_calls();
+        let outer = profiler.get_subroutine(0x300).unwrap();
+        assert_eq!((outer.calls, outer.inclusive, outer.exclusive), (1, 5, 3));
+        let inner = profiler.get_subroutine(0x400).unwrap();
+        assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (1, 2, 2));
+        assert_eq!(profiler.get_subroutine(0x200), None);
+    }
+
+    #[test]
+    fn profiler_output() {
+        let profiler = profile_calls();
+        assert_eq!(profiler.get_folded_stacks(), "main 4\nmain;sub_300 3\nmain;sub_300;sub_400 2\n");
+        let json = profiler.get_json();
+        assert!(json.starts_with("{\"instructions\":9,\"classes\":{\"display\":1,\"flow\":6,"));
+        assert!(json.contains("\"subroutines\":[{\"address\":768,\"calls\":1,\"inclusive\":5,\"exclusive\":3}"));
+    }
+}
@@ -0,0 +1,159 @@
+use wasm_bindgen::prelude::*;
+
+// The original interpreters disagree on the behaviour of a handful of instructions, and ROMs
+// written for one platform often misbehave on another. These flags select which behaviour the
+// CPU uses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    //8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    //FX55 and FX65 leave I pointing past the last register that was stored or loaded
+    pub memory_increment: bool,
+    //8XY6 and 8XYE shift VY and store the result in VX, rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    //BXNN jumps to XNN + VX rather than NNN + V0
+    pub jump_uses_vx: bool,
+    //sprites are clipped at the edges of the screen rather than wrapping around to the other side
+    pub clip_sprites: bool,
+    //DXYN waits for the start of the next frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+    //8XY4 to 8XYE write VF before VX rather than after it, so when X is F the result is kept instead of the flag
+    pub flag_written_first: bool,
+    //what happens when pc or I is moved past the end of the 12 bit address space
+    pub address_overflow: AddressOverflow,
+    //how many CALLs can be nested
+    pub stack_depth: usize,
+    //what CALL does when the stack is full, and RET when it is empty
+    pub stack_overflow: StackOverflow,
+    //where the stack is kept in memory, two bytes per entry with the oldest first, as the original
+    //interpreter kept it in the RAM above the program. None keeps it out of memory.
+    pub stack_address: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressOverflow {
+    //wrap around to the start of memory, as the original hardware does
+    Wrap,
+    //stop with a CPUError, which is more useful when debugging a ROM
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackOverflow {
+    //stop with a CPUError
+    Error,
+    //the stack pointer wraps around, so a full stack overwrites its oldest entry and an empty one
+    //returns to its newest
+    Wrap,
+    //CALL and RET do nothing and leave pc where it is, so the ROM stays on the instruction that overflowed
+    Halt,
+}
+
+pub const STACK_OVERFLOWS: [StackOverflow; 3] = [StackOverflow::Error, StackOverflow::Wrap, StackOverflow::Halt];
+
+impl StackOverflow {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            StackOverflow::Error => "error",
+            StackOverflow::Wrap => "wrap",
+            StackOverflow::Halt => "halt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        STACK_OVERFLOWS.iter().find(|overflow| overflow.get_name() == name).copied()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuirkPreset {
+    //the behaviour this emulator has always had, which suits most of the ROMs in web/roms
+    Modern = 0,
+    CosmacVip = 1,
+    Schip = 2,
+    XoChip = 3,
+}
+
+pub const QUIRK_PRESETS: [QuirkPreset; 4] = [QuirkPreset::Modern, QuirkPreset::CosmacVip, QuirkPreset::Schip, QuirkPreset::XoChip];
+
+impl Quirks {
+    pub fn new(preset: QuirkPreset) -> Self {
+        match preset {
+            QuirkPreset::Modern => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                jump_uses_vx: false,
+                clip_sprites: false,
+                display_wait: false,
+                flag_written_first: false,
+                address_overflow: AddressOverflow::Wrap,
+                stack_depth: 16,
+                stack_overflow: StackOverflow::Error,
+                stack_address: None,
+            },
+            QuirkPreset::CosmacVip => Quirks {
+                vf_reset: true,
+                memory_increment: true,
+                shift_uses_vy: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+                display_wait: true,
+                flag_written_first: false,
+                address_overflow: AddressOverflow::Wrap,
+                stack_depth: 12,
+                stack_overflow: StackOverflow::Error,
+                stack_address: None,
+            },
+            QuirkPreset::Schip => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                display_wait: false,
+                flag_written_first: false,
+                address_overflow: AddressOverflow::Wrap,
+                stack_depth: 16,
+                stack_overflow: StackOverflow::Error,
+                stack_address: None,
+            },
+            QuirkPreset::XoChip => Quirks {
+                vf_reset: false,
+                memory_increment: true,
+                shift_uses_vy: true,
+                jump_uses_vx: false,
+                clip_sprites: false,
+                display_wait: false,
+                flag_written_first: false,
+                address_overflow: AddressOverflow::Wrap,
+                //Octo doesn't limit the stack, so XO-CHIP ROMs get more room than the older platforms gave
+                stack_depth: 32,
+                stack_overflow: StackOverflow::Error,
+                stack_address: None,
+            },
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::new(QuirkPreset::Modern)
+    }
+}
+
+impl QuirkPreset {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            QuirkPreset::Modern => "modern",
+            QuirkPreset::CosmacVip => "vip",
+            QuirkPreset::Schip => "schip",
+            QuirkPreset::XoChip => "xo-chip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        QUIRK_PRESETS.iter().find(|preset| preset.get_name() == name).copied()
+    }
+}
@@ -0,0 +1,230 @@
+// Identifies ROMs by their SHA-1, and looks up the settings they need to run properly: the quirks
+// of the platform they were written for, how many instructions to run each frame, and which keys
+// do what.
+//
+// Databases are read in the format of the community chip-8-database's programs.json: a list of
+// programs, each with a title, optional authors, and a map from the SHA-1 of each version of the
+// program to its settings. The embedded database only covers the ROMs in web/roms, with the
+// settings they have always been run with here, but a copy of the full database can be loaded on
+// top of it.
+
+use super::json::{JsonError, Value};
+use super::quirks::{QuirkPreset, Quirks};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+const EMBEDDED_DATABASE: &str = include_str!("../data/roms.json");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    //the chip-8-database's id for the first platform the ROM runs on, like originalChip8
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    //instructions per frame
+    pub tickrate: Option<usize>,
+    //what each key does, like ("up", 0x5)
+    pub keys: Vec<(String, u8)>,
+}
+
+impl RomInfo {
+    pub fn get_key(&self, action: &str) -> Option<u8> {
+        self.keys.iter().find(|(name, _)| name == action).map(|(_, key)| *key)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RomDatabase {
+    //keyed by the SHA-1 of the ROM, in lower case hex
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        RomDatabase::default()
+    }
+
+    pub fn embedded() -> Self {
+        RomDatabase::parse(EMBEDDED_DATABASE).expect("the embedded ROM database is invalid")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, DatabaseError> {
+        let json = Value::parse(text).map_err(DatabaseError::InvalidJson)?;
+        let mut database = RomDatabase::new();
+        for program in json.as_array().ok_or(DatabaseError::InvalidFormat)? {
+            let title = program.get("title").and_then(Value::as_str).ok_or(DatabaseError::InvalidFormat)?;
+            let authors: Vec<String> = match program.get("authors").and_then(Value::as_array) {
+                Some(authors) => authors.iter().filter_map(Value::as_str).map(String::from).collect(),
+                None => Vec::new(),
+            };
+            for (hash, rom) in program.get("roms").and_then(Value::as_object).ok_or(DatabaseError::InvalidFormat)? {
+                let info = RomInfo {
+                    title: String::from(title),
+                    authors: authors.clone(),
+                    platform: get_platform(rom).map(String::from),
+                    quirks: get_quirks(rom),
+                    tickrate: rom.get("tickrate").and_then(Value::as_f64).map(|tickrate| tickrate as usize),
+                    keys: get_keys(rom),
+                };
+                database.roms.insert(hash.to_lowercase(), info);
+            }
+        }
+        Ok(database)
+    }
+
+    // Adds the ROMs from another database, replacing any this one already has
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&RomInfo> {
+        self.roms.get(&hash.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+// The closest quirk preset to each of the chip-8-database's platforms
+pub fn get_platform_preset(platform: &str) -> Option<QuirkPreset> {
+    match platform {
+        "originalChip8" | "hybridVIP" | "chip8x" => Some(QuirkPreset::CosmacVip),
+        "modernChip8" => Some(QuirkPreset::Modern),
+        "chip48" | "superchip1" | "superchip" | "megachip8" => Some(QuirkPreset::Schip),
+        "xochip" => Some(QuirkPreset::XoChip),
+        _ => None,
+    }
+}
+
+fn get_platform(rom: &Value) -> Option<&str> {
+    rom.get("platforms")?.as_array()?.first()?.as_str()
+}
+
+// The platform's quirks, with any the ROM overrides for that platform changed
+fn get_quirks(rom: &Value) -> Option<Quirks> {
+    let platform = get_platform(rom)?;
+    let mut quirks = Quirks::new(get_platform_preset(platform)?);
+    let overrides = rom.get("quirkyPlatforms").and_then(|platforms| platforms.get(platform)).and_then(Value::as_object);
+    for (name, value) in overrides.unwrap_or(&[]) {
+        let value = match value.as_bool() {
+            Some(value) => value,
+            None => continue,
+        };
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !value,
+            "memoryLeaveIUnchanged" => quirks.memory_increment = !value,
+            //I can only be left past the last register, rather than just on it
+            "memoryIncrementByX" => quirks.memory_increment = value,
+            "wrap" => quirks.clip_sprites = !value,
+            "jump" => quirks.jump_uses_vx = value,
+            "vblank" => quirks.display_wait = value,
+            "logic" => quirks.vf_reset = value,
+            _ => {}
+        }
+    }
+    Some(quirks)
+}
+
+fn get_keys(rom: &Value) -> Vec<(String, u8)> {
+    let keys = rom.get("keys").and_then(Value::as_object).unwrap_or(&[]);
+    keys.iter()
+        .filter_map(|(action, key)| match key.as_f64() {
+            Some(key) if (0.0..16.0).contains(&key) => Some((action.clone(), key as u8)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DatabaseError {
+    InvalidJson(JsonError),
+    //the JSON isn't a list of programs, or a program is missing its title or ROMs
+    InvalidFormat,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DatabaseError::InvalidJson(e) => write!(f, "{}", e),
+            DatabaseError::InvalidFormat => write!(f, "Not a ROM database, expected a list of programs with a title and ROMs"),
+        }
+    }
+}
+
+impl error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DatabaseError::InvalidJson(e) => Some(e),
+            DatabaseError::InvalidFormat => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod romdb_tests {
+    use super::*;
+    use crate::sha1::sha1_hex;
+
+    const DATABASE: &str = r#"[
+  {
+    "title": "Test Game",
+    "authors": ["Someone", "Someone Else"],
+    "roms": {
+      "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+        "file": "test.ch8",
+        "platforms": ["originalChip8", "modernChip8"],
+        "quirkyPlatforms": { "originalChip8": { "vblank": false, "shift": true }, "modernChip8": { "wrap": true } },
+        "tickrate": 15,
+        "keys": { "up": 5, "down": 8, "a": 99 }
+      },
+      "0000000000000000000000000000000000000000": { "platforms": ["someFutureChip8"] }
+    }
+  }
+]"#;
+
+    #[test]
+    fn romdb_parse() {
+        let database = RomDatabase::parse(DATABASE).unwrap();
+        assert_eq!(database.len(), 2);
+
+        let info = database.get(&sha1_hex(b"abc")).unwrap();
+        assert_eq!(info.title, "Test Game");
+        assert_eq!(info.authors, vec!["Someone", "Someone Else"]);
+        assert_eq!(info.platform.as_deref(), Some("originalChip8"));
+        assert_eq!(info.tickrate, Some(15));
+        assert_eq!(info.get_key("up"), Some(5));
+        assert_eq!(info.get_key("a"), None);
+
+        let mut quirks = Quirks::new(QuirkPreset::CosmacVip);
+        quirks.display_wait = false;
+        quirks.shift_uses_vy = false;
+        assert_eq!(info.quirks, Some(quirks));
+
+        let unknown = database.get("0000000000000000000000000000000000000000").unwrap();
+        assert_eq!(unknown.quirks, None);
+        assert_eq!(unknown.tickrate, None);
+
+        assert!(matches!(RomDatabase::parse("[{"), Err(DatabaseError::InvalidJson(_))));
+        assert_eq!(RomDatabase::parse("{}").unwrap_err(), DatabaseError::InvalidFormat);
+        assert_eq!(RomDatabase::parse("[{\"title\": \"x\"}]").unwrap_err(), DatabaseError::InvalidFormat);
+    }
+
+    #[test]
+    fn romdb_embedded() {
+        let mut database = RomDatabase::embedded();
+        let pong = database.get("b232ef880bd6060fb45fa6effed7edf0ae95670e").unwrap();
+        assert_eq!(pong.title, "Pong");
+        assert_eq!(pong.quirks, Some(Quirks::default()));
+
+        let count = database.len();
+        database.extend(RomDatabase::parse(DATABASE).unwrap());
+        assert_eq!(database.len(), count + 2);
+    }
+}
@@ -0,0 +1,238 @@
+// Draws a region of memory as if it were sprites, to make the graphics in a ROM easy to find.
+// CHIP-8 sprites are one byte wide, so the region is cut into strips that are 8 pixels wide and a
+// chosen number of rows tall. SCHIP's large sprites are 16x16 pixels, two bytes to a row.
+
+use super::display::{BYTES_PER_RGBA_PIXEL, MAX_SCALE};
+use super::memory::{Memory, MEMORY_SIZE};
+use super::palette::{Colour, Palette};
+use super::png;
+use wasm_bindgen::prelude::*;
+
+const SCHIP_SPRITE_ROWS: usize = 16;
+const GAP: usize = 1; //pixels between sprites, before scaling
+const GAP_COLOUR: Colour = Colour::new(0x40, 0x40, 0x40);
+const HIGHLIGHT_FOREGROUND: Colour = Colour::new(0xFF, 0x40, 0x40);
+const HIGHLIGHT_BACKGROUND: Colour = Colour::new(0x50, 0x00, 0x00);
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpriteFormat {
+    Chip8 = 0,
+    Schip = 1,
+}
+
+impl SpriteFormat {
+    pub fn get_width(self) -> usize {
+        match self {
+            SpriteFormat::Chip8 => 8,
+            SpriteFormat::Schip => 16,
+        }
+    }
+
+    pub fn get_bytes_per_row(self) -> usize {
+        self.get_width() / 8
+    }
+}
+
+// Which part of memory to draw and how to arrange it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteLayout {
+    pub start: usize,
+    pub length: usize,
+    pub format: SpriteFormat,
+    //how tall each strip is, ignored for SCHIP sprites, which are always 16 rows
+    pub rows: usize,
+    //how many strips are drawn side by side before starting a new line of them
+    pub columns: usize,
+    pub scale: usize,
+}
+
+impl SpriteLayout {
+    fn get_rows(&self) -> usize {
+        match self.format {
+            //no strip can be taller than memory
+            SpriteFormat::Chip8 => self.rows.clamp(1, MEMORY_SIZE),
+            SpriteFormat::Schip => SCHIP_SPRITE_ROWS,
+        }
+    }
+
+    fn get_bytes_per_sprite(&self) -> usize {
+        self.get_rows() * self.format.get_bytes_per_row()
+    }
+
+    fn get_sprite_count(&self) -> usize {
+        let length = self.length.min(MEMORY_SIZE.saturating_sub(self.start));
+        length.div_ceil(self.get_bytes_per_sprite())
+    }
+}
+
+#[wasm_bindgen]
+pub struct SpriteSheet {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl SpriteSheet {
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_rgba(&self) -> Vec<u8> {
+        self.rgba.clone()
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgba(self.width, self.height, &self.rgba)
+    }
+}
+
+impl SpriteSheet {
+    // Draws the sprites, with the bytes between highlight's start and end (exclusive) in red
+    pub fn render(memory: &Memory, layout: &SpriteLayout, palette: &Palette, highlight: Option<(usize, usize)>) -> Self {
+        let scale = layout.scale.clamp(1, MAX_SCALE);
+        let columns = layout.columns.clamp(1, MEMORY_SIZE);
+        let sprite_width = layout.format.get_width();
+        let sprite_height = layout.get_rows();
+        let sprites = layout.get_sprite_count();
+        let grid_columns = columns.min(sprites.max(1));
+        let grid_rows = sprites.div_ceil(columns);
+
+        //each sprite has a gap on its left and top, and the last ones have one on the right and bottom
+        let width = (grid_columns * (sprite_width + GAP) + GAP) * scale;
+        let height = (grid_rows.max(1) * (sprite_height + GAP) + GAP) * scale;
+        let mut rgba = Vec::with_capacity(width * height * BYTES_PER_RGBA_PIXEL);
+        let end = layout.start.saturating_add(layout.length).min(MEMORY_SIZE);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (column, pixel_x) = ((x / scale) / (sprite_width + GAP), (x / scale) % (sprite_width + GAP));
+                let (row, pixel_y) = ((y / scale) / (sprite_height + GAP), (y / scale) % (sprite_height + GAP));
+                let sprite = row * columns + column;
+                let colour = if pixel_x < GAP || pixel_y < GAP || column >= grid_columns || sprite >= sprites {
+                    GAP_COLOUR
+                } else {
+                    let (pixel_x, pixel_y) = (pixel_x - GAP, pixel_y - GAP);
+                    let bytes_per_row = layout.format.get_bytes_per_row();
+                    let address = layout.start + sprite * layout.get_bytes_per_sprite() + pixel_y * bytes_per_row + pixel_x / 8;
+                    if address >= end {
+                        GAP_COLOUR
+                    } else {
+                        let lit = memory.memory[address] & (0x80 >> (pixel_x % 8)) != 0;
+                        let highlighted = highlight.is_some_and(|(start, end)| address >= start && address < end);
+                        match (highlighted, lit) {
+                            (true, true) => HIGHLIGHT_FOREGROUND,
+                            (true, false) => HIGHLIGHT_BACKGROUND,
+                            (false, true) => palette.get_foreground(),
+                            (false, false) => palette.get_background(),
+                        }
+                    }
+                };
+                rgba.extend_from_slice(&colour.to_rgba());
+            }
+        }
+
+        SpriteSheet { width, height, rgba }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Colour {
+        let start = (y * self.width + x) * BYTES_PER_RGBA_PIXEL;
+        Colour::new(self.rgba[start], self.rgba[start + 1], self.rgba[start + 2])
+    }
+}
+
+#[cfg(test)]
+mod sprites_tests {
+    use super::*;
+
+    fn layout(format: SpriteFormat, length: usize, rows: usize, columns: usize) -> SpriteLayout {
+        SpriteLayout {
+            start: 0x300,
+            length,
+            format,
+            rows,
+            columns,
+            scale: 1,
+        }
+    }
+
+    #[test]
+    fn sprites_chip8_strips() {
+        let mut memory = Memory::new();
+        memory.memory[0x300] = 0x80;
+        memory.memory[0x305] = 0x01;
+        let palette = Palette::default();
+
+        //two strips of five rows, side by side
+        let sheet = SpriteSheet::render(&memory, &layout(SpriteFormat::Chip8, 10, 5, 4), &palette, None);
+        assert_eq!((sheet.get_width(), sheet.get_height()), (2 * 9 + 1, 5 + 2));
+        assert_eq!(sheet.get_pixel(0, 1), GAP_COLOUR);
+        assert_eq!(sheet.get_pixel(1, 1), palette.get_foreground());
+        assert_eq!(sheet.get_pixel(2, 1), palette.get_background());
+        assert_eq!(sheet.get_pixel(10 + 7, 1), palette.get_foreground());
+
+        //the second strip wraps onto a new line, and the rest of that line is empty
+        let sheet = SpriteSheet::render(&memory, &layout(SpriteFormat::Chip8, 10, 5, 1), &palette, None);
+        assert_eq!((sheet.get_width(), sheet.get_height()), (10, 2 * 6 + 1));
+        assert_eq!(sheet.get_pixel(8, 7), palette.get_foreground());
+    }
+
+    #[test]
+    fn sprites_schip_and_highlight() {
+        let mut memory = Memory::new();
+        memory.memory[0x301] = 0x01;
+        memory.memory[0x302] = 0x80;
+        let palette = Palette::default();
+
+        let sheet = SpriteSheet::render(&memory, &layout(SpriteFormat::Schip, 32, 0, 4), &palette, Some((0x302, 0x304)));
+        assert_eq!((sheet.get_width(), sheet.get_height()), (18, 18));
+        assert_eq!(sheet.get_pixel(16, 1), palette.get_foreground());
+        assert_eq!(sheet.get_pixel(1, 2), HIGHLIGHT_FOREGROUND);
+        assert_eq!(sheet.get_pixel(2, 2), HIGHLIGHT_BACKGROUND);
+        assert_eq!(sheet.get_pixel(1, 3), palette.get_background());
+    }
+
+    #[test]
+    fn sprites_scale_and_partial() {
+        let memory = Memory::new();
+        let mut layout = layout(SpriteFormat::Chip8, 3, 2, 4);
+        layout.scale = 2;
+        let sheet = SpriteSheet::render(&memory, &layout, &Palette::default(), None);
+        assert_eq!((sheet.get_width(), sheet.get_height()), ((2 * 9 + 1) * 2, (2 + 2) * 2));
+        assert_eq!(sheet.get_rgba().len(), sheet.get_width() * sheet.get_height() * BYTES_PER_RGBA_PIXEL);
+        //the fourth byte of the second strip is past the end of the region
+        assert_eq!(sheet.get_pixel(11 * 2, 2 * 2), GAP_COLOUR);
+        assert!(!sheet.to_png().is_empty());
+    }
+
+    #[test]
+    fn sprites_extreme_layout() {
+        let memory = Memory::new();
+        let palette = Palette::default();
+        let extreme = |start: usize, length: usize, rows: usize, columns: usize| SpriteLayout {
+            start,
+            length,
+            format: SpriteFormat::Chip8,
+            rows,
+            columns,
+            scale: usize::MAX,
+        };
+
+        //one strip as tall as memory
+        let layout = SpriteLayout {
+            scale: 1,
+            ..extreme(0, usize::MAX, usize::MAX, usize::MAX)
+        };
+        let sheet = SpriteSheet::render(&memory, &layout, &palette, None);
+        assert_eq!((sheet.get_width(), sheet.get_height()), (10, MEMORY_SIZE + 2));
+
+        //nothing to draw past the end of memory
+        let sheet = SpriteSheet::render(&memory, &extreme(usize::MAX, usize::MAX, 1, usize::MAX), &palette, None);
+        assert_eq!((sheet.get_width(), sheet.get_height()), (10 * MAX_SCALE, 3 * MAX_SCALE));
+    }
+}
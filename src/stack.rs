@@ -1,37 +1,91 @@
+use super::quirks::StackOverflow;
 use std::error;
 use std::fmt;
 
-const STACK_SIZE: usize = 16; //support up to 16 levels
+pub const STACK_SIZE: usize = 16; //support up to 16 levels unless the quirks ask for something else
+pub const MAX_STACK_DEPTH: usize = 256; //far deeper than any interpreter went, and small enough to keep in memory
 
 pub struct Stack {
-    stack: [u16; STACK_SIZE],
+    stack: Vec<u16>,
     sp: usize,
+    //what push and pop do when the stack is full or empty
+    overflow: StackOverflow,
 }
 
 impl Stack {
     pub fn new() -> Self {
         Stack {
-            stack: [0; STACK_SIZE],
+            stack: vec![0; STACK_SIZE],
             sp: 0,
+            overflow: StackOverflow::Error,
         }
     }
 
-    pub fn push(&mut self, value: u16) -> Result<(), StackError> {
-        if self.sp == (STACK_SIZE - 1) {
-            return Err(StackError::PushToFullStack);
+    // Changes the number of levels, dropping the newest entries if there are too many to keep
+    pub fn configure(&mut self, depth: usize, overflow: StackOverflow) {
+        let depth = depth.clamp(1, MAX_STACK_DEPTH);
+        self.stack.resize(depth, 0);
+        self.sp = self.sp.min(depth);
+        self.overflow = overflow;
+    }
+
+    pub fn clear(&mut self) {
+        self.sp = 0;
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // The entry the next push will write to, without pushing
+    pub fn get_push_index(&self) -> Result<usize, StackError> {
+        if self.sp < self.stack.len() {
+            return Ok(self.sp);
+        }
+        match self.overflow {
+            //the stack pointer wraps around, so the oldest entry is overwritten
+            StackOverflow::Wrap => Ok(0),
+            StackOverflow::Error | StackOverflow::Halt => Err(StackError::PushToFullStack),
         }
-        self.stack[self.sp] = value;
-        self.sp = self.sp + 1;
+    }
+
+    // The entry the next pop will return, without popping
+    pub fn get_pop_index(&self) -> Result<usize, StackError> {
+        if self.sp > 0 {
+            return Ok(self.sp - 1);
+        }
+        match self.overflow {
+            StackOverflow::Wrap => Ok(self.stack.len() - 1),
+            StackOverflow::Error | StackOverflow::Halt => Err(StackError::PopFromEmptyStack),
+        }
+    }
+
+    pub fn push(&mut self, value: u16) -> Result<(), StackError> {
+        let index = self.get_push_index()?;
+        self.stack[index] = value;
+        self.sp = index + 1;
         Ok(())
     }
 
+    // The value the next pop will return, without popping
+    pub fn peek(&self) -> Result<u16, StackError> {
+        Ok(self.stack[self.get_pop_index()?])
+    }
+
     pub fn pop(&mut self) -> Result<u16, StackError> {
-        if self.sp == 0 {
-            return Err(StackError::PopFromEmptyStack);
-        }
-        self.sp = self.sp - 1;
-        let return_value = self.stack[self.sp];
-        Ok(return_value)
+        let index = self.get_pop_index()?;
+        self.sp = index;
+        Ok(self.stack[index])
+    }
+
+    pub fn get_entries(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Stack::new()
     }
 }
 
@@ -63,4 +117,43 @@ mod stack_tests {
         let popped_value = stack.pop().unwrap();
         assert_eq!(popped_value, 0x1111);
     }
+
+    #[test]
+    fn stack_full() {
+        let mut stack = Stack::new();
+        for i in 0..STACK_SIZE {
+            stack.push(i as u16).unwrap();
+        }
+        assert!(stack.push(0x1111).is_err());
+        assert_eq!(stack.get_entries().len(), STACK_SIZE);
+        assert_eq!(stack.pop().unwrap(), (STACK_SIZE - 1) as u16);
+    }
+
+    #[test]
+    fn stack_wrap() {
+        let mut stack = Stack::new();
+        stack.configure(2, StackOverflow::Wrap);
+        for i in 0..3 {
+            stack.push(i).unwrap();
+        }
+        //the third push overwrote the first
+        assert_eq!(stack.get_entries(), &[2]);
+        assert_eq!(stack.pop().unwrap(), 2);
+        assert_eq!(stack.pop().unwrap(), 1);
+        assert_eq!(stack.pop().unwrap(), 2);
+
+        stack.configure(1, StackOverflow::Error);
+        stack.push(3).unwrap();
+        assert!(stack.push(4).is_err());
+        assert_eq!(stack.get_entries(), &[3]);
+    }
+
+    #[test]
+    fn stack_depth_limit() {
+        let mut stack = Stack::new();
+        stack.configure(usize::MAX, StackOverflow::Error);
+        assert_eq!(stack.get_depth(), MAX_STACK_DEPTH);
+        stack.configure(0, StackOverflow::Error);
+        assert_eq!(stack.get_depth(), 1);
+    }
 }
@@ -0,0 +1,250 @@
+// Names, comments and data regions for the addresses in a ROM, so listings and traces can show
+// draw_paddle instead of 0x2A4.
+//
+// Symbol files are plain text with one entry per line. Lines starting with # or ; are ignored.
+//     2A4 draw_paddle              a label, the address is hex with an optional 0x prefix
+//     :label draw_paddle 0x2A4     a label
+//     :breakpoint name 0x2A4       also a label
+//     :const NAME 5                ignored, since constants aren't addresses (as is :alias)
+//
+// This is the crate's own format. It borrows some directive names from Octo, but Octo's source and
+// exports can't be loaded as symbol files.
+//     :comment 0x2A4 some text     a comment shown next to the instruction
+//     :data 0x300 0x30F [name]     bytes that are data, inclusive at both ends, with an optional label
+
+use super::analysis::Analysis;
+use super::disassembler::Instruction;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    labels: BTreeMap<u16, String>,
+    comments: BTreeMap<u16, String>,
+    //start and end of each region, inclusive
+    data_regions: Vec<(u16, u16)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = SymbolTable::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let invalid = SymbolError::InvalidLine(index + 1);
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some(":label") | Some(":breakpoint") => {
+                    let name = fields.next().ok_or(invalid)?;
+                    let address = fields.next().and_then(parse_address).ok_or(invalid)?;
+                    symbols.add_label(address, name);
+                }
+                Some(":comment") => {
+                    let address = fields.next().and_then(parse_address).ok_or(invalid)?;
+                    let text = fields.collect::<Vec<&str>>().join(" ");
+                    symbols.add_comment(address, &text);
+                }
+                Some(":data") => {
+                    let start = fields.next().and_then(parse_address).ok_or(invalid)?;
+                    let end = fields.next().and_then(parse_address).ok_or(invalid)?;
+                    if end < start {
+                        return Err(invalid);
+                    }
+                    symbols.add_data_region(start, end);
+                    if let Some(name) = fields.next() {
+                        symbols.add_label(start, name);
+                    }
+                }
+                //other directives, like :const and :alias, aren't about addresses
+                Some(directive) if directive.starts_with(':') => {}
+                Some(address) => {
+                    let address = parse_address(address).ok_or(invalid)?;
+                    let name = fields.next().ok_or(invalid)?;
+                    symbols.add_label(address, name);
+                }
+                None => {}
+            }
+        }
+        Ok(symbols)
+    }
+
+    // Generates a label for the entry point and every subroutine, and marks the bytes between start
+    // and end that can't be reached as data. This is a starting point for naming things by hand.
+    pub fn from_analysis(analysis: &Analysis, start: u16, end: u16) -> Self {
+        let mut symbols = SymbolTable::new();
+        symbols.add_label(analysis.get_entry(), "main");
+        for address in analysis.get_subroutines().iter() {
+            if *address != analysis.get_entry() {
+                symbols.add_label(*address, &format!("sub_{:03X}", address));
+            }
+        }
+        let mut address = start;
+        while address < end {
+            if analysis.is_code(address) {
+                address += 1;
+                continue;
+            }
+            let region_start = address;
+            while address < end && !analysis.is_code(address) {
+                address += 1;
+            }
+            symbols.add_data_region(region_start, address - 1);
+        }
+        symbols
+    }
+
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.labels.insert(address, String::from(name));
+    }
+
+    pub fn add_comment(&mut self, address: u16, text: &str) {
+        self.comments.insert(address, String::from(text));
+    }
+
+    pub fn add_data_region(&mut self, start: u16, end: u16) {
+        self.data_regions.push((start, end));
+    }
+
+    pub fn get_label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn get_comment(&self, address: u16) -> Option<&str> {
+        self.comments.get(&address).map(String::as_str)
+    }
+
+    pub fn is_data(&self, address: u16) -> bool {
+        self.data_regions.iter().any(|(start, end)| address >= *start && address <= *end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.comments.is_empty() && self.data_regions.is_empty()
+    }
+
+    // The label for an address, or the address in hex if it doesn't have one
+    pub fn format_address(&self, address: u16) -> String {
+        match self.get_label(address) {
+            Some(label) => String::from(label),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    // Disassembles an instruction, using labels for the addresses it refers to
+    pub fn format_instruction(&self, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Jp(address) => format!("JP {}", self.format_address(address)),
+            Instruction::Call(address) => format!("CALL {}", self.format_address(address)),
+            Instruction::LdI(address) => format!("LD I, {}", self.format_address(address)),
+            Instruction::JpV0(address) => format!("JP V0, {}", self.format_address(address)),
+            _ => instruction.to_string(),
+        }
+    }
+}
+
+// Writes the table back out in the format parse reads
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, name) in self.labels.iter() {
+            if !self.data_regions.iter().any(|(start, _)| start == address) {
+                writeln!(f, ":label {} {:#05X}", name, address)?;
+            }
+        }
+        for (start, end) in self.data_regions.iter() {
+            match self.labels.get(start) {
+                Some(name) => writeln!(f, ":data {:#05X} {:#05X} {}", start, end, name)?,
+                None => writeln!(f, ":data {:#05X} {:#05X}", start, end)?,
+            }
+        }
+        for (address, text) in self.comments.iter() {
+            writeln!(f, ":comment {:#05X} {}", address, text)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolError {
+    InvalidLine(usize),
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SymbolError::InvalidLine(line) => write!(f, "Invalid symbol on line {}", line),
+        }
+    }
+}
+
+impl error::Error for SymbolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod symbols_tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    const SYMBOLS: &str = "# symbols for a test ROM
+2A4 draw_paddle
+0x2B0 move_ball
+:label main 0x200
+:breakpoint hit_wall 0x2C0
+:const PADDLE_HEIGHT 5
+:comment 0x2A4 draws the paddle at VA, VB
+:data 0x300 0x30F paddle_sprite
+";
+
+    #[test]
+    fn symbols_parse() {
+        let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+        assert_eq!(symbols.get_label(0x200), Some("main"));
+        assert_eq!(symbols.get_label(0x2A4), Some("draw_paddle"));
+        assert_eq!(symbols.get_label(0x2B0), Some("move_ball"));
+        assert_eq!(symbols.get_label(0x2C0), Some("hit_wall"));
+        assert_eq!(symbols.get_label(0x300), Some("paddle_sprite"));
+        assert_eq!(symbols.get_label(0x5), None);
+        assert_eq!(symbols.get_comment(0x2A4), Some("draws the paddle at VA, VB"));
+        assert!(symbols.is_data(0x300));
+        assert!(symbols.is_data(0x30F));
+        assert!(!symbols.is_data(0x310));
+
+        assert_eq!(SymbolTable::parse(&symbols.to_string()), Ok(symbols));
+
+        assert_eq!(SymbolTable::parse("2A4"), Err(SymbolError::InvalidLine(1)));
+        assert_eq!(SymbolTable::parse("\n:data 0x300"), Err(SymbolError::InvalidLine(2)));
+        assert_eq!(SymbolTable::parse("zz paddle"), Err(SymbolError::InvalidLine(1)));
+    }
+
+    #[test]
+    fn symbols_format_instruction() {
+        let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+        assert_eq!(symbols.format_instruction(Instruction::Call(0x2A4)), "CALL draw_paddle");
+        assert_eq!(symbols.format_instruction(Instruction::LdI(0x300)), "LD I, paddle_sprite");
+        assert_eq!(symbols.format_instruction(Instruction::Jp(0x2A6)), "JP 0x2A6");
+        assert_eq!(symbols.format_instruction(Instruction::Drw(0, 1, 5)), "DRW V0, V1, 5");
+    }
+
+    #[test]
+    fn symbols_from_analysis() {
+        let mut memory = Memory::new();
+        //CALL 0x206, JP 0x202, data, LD I 0x200, RET
+        memory.load_program(&[0x22, 0x06, 0x12, 0x02, 0xAB, 0xCD, 0xA2, 0x00, 0x00, 0xEE]).unwrap();
+        let analysis = Analysis::new(&memory, 0x200);
+        let symbols = SymbolTable::from_analysis(&analysis, 0x200, 0x20A);
+        assert_eq!(symbols.to_string(), ":label main 0x200\n:label sub_206 0x206\n:data 0x204 0x205\n");
+    }
+}
@@ -0,0 +1,275 @@
+// Records every instruction the CPU executes, along with the registers it changed. Entries go into
+// a ring buffer, so a long running ROM only keeps its most recent history.
+
+use super::cpu::{CPU, REGISTER_COUNT};
+use super::disassembler::{Instruction, OpcodeClass};
+use super::symbols::SymbolTable;
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    V(usize),
+    I,
+    DelayTimer,
+    SoundTimer,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(index) => write!(f, "V{:X}", index),
+            Register::I => write!(f, "I"),
+            Register::DelayTimer => write!(f, "DT"),
+            Register::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub before: u16,
+    pub after: u16,
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.register {
+            Register::I => write!(f, "{}={:03X}->{:03X}", self.register, self.before, self.after),
+            _ => write!(f, "{}={:02X}->{:02X}", self.register, self.before, self.after),
+        }
+    }
+}
+
+// The registers an instruction can change, taken before and after it runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegisterSnapshot {
+    pub v: [u8; REGISTER_COUNT],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl RegisterSnapshot {
+    pub fn new(cpu: &CPU) -> Self {
+        let mut v = [0; REGISTER_COUNT];
+        v.copy_from_slice(cpu.get_v_registers());
+        RegisterSnapshot {
+            v,
+            i: cpu.get_i(),
+            delay_timer: cpu.get_delay_timer(),
+            sound_timer: cpu.get_sound_timer(),
+        }
+    }
+
+    pub fn get_changes(&self, after: &RegisterSnapshot) -> Vec<RegisterChange> {
+        let mut changes = Vec::new();
+        for (index, (before, after)) in self.v.iter().zip(after.v.iter()).enumerate() {
+            if before != after {
+                changes.push(RegisterChange {
+                    register: Register::V(index),
+                    before: *before as u16,
+                    after: *after as u16,
+                });
+            }
+        }
+        let others = [
+            (Register::I, self.i, after.i),
+            (Register::DelayTimer, self.delay_timer as u16, after.delay_timer as u16),
+            (Register::SoundTimer, self.sound_timer as u16, after.sound_timer as u16),
+        ];
+        for (register, before, after) in others.iter() {
+            if before != after {
+                changes.push(RegisterChange {
+                    register: *register,
+                    before: *before,
+                    after: *after,
+                });
+            }
+        }
+        changes
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub changes: Vec<RegisterChange>,
+}
+
+impl TraceEntry {
+    // The same line as to_string, with labels in place of addresses, and the label of the
+    // instruction itself at the end if it has one
+    pub fn to_string_with_symbols(&self, symbols: &SymbolTable) -> String {
+        let disassembly = symbols.format_instruction(Instruction::decode(self.opcode));
+        let mut line = format!("{:04X}  {:04X}  {}", self.pc, self.opcode, disassembly);
+        if !self.changes.is_empty() {
+            line = format!("{:<30}", line);
+            for change in self.changes.iter() {
+                line.push_str(&format!(" {}", change));
+            }
+        }
+        if let Some(label) = symbols.get_label(self.pc) {
+            line.push_str(&format!("  ; {}", label));
+        }
+        line
+    }
+}
+
+// One line per instruction, for example "0202  6A02  LD VA, 0x02        VA=00->02"
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string_with_symbols(&SymbolTable::new()))
+    }
+}
+
+// An instruction is traced if it falls inside the address range and belongs to one of the classes.
+// No range and no classes means everything is traced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    //inclusive at both ends
+    pub address_range: Option<(u16, u16)>,
+    pub classes: Vec<OpcodeClass>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        if let Some((start, end)) = self.address_range {
+            if pc < start || pc > end {
+                return false;
+            }
+        }
+        self.classes.is_empty() || self.classes.contains(&Instruction::decode(opcode).get_class())
+    }
+}
+
+pub struct Tracer {
+    capacity: usize,
+    filter: TraceFilter,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Tracer {
+            capacity,
+            filter: TraceFilter::default(),
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn get_filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    pub fn is_traced(&self, pc: u16, opcode: u16) -> bool {
+        self.filter.matches(pc, opcode)
+    }
+
+    //the oldest entry is dropped once the buffer is full
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn get_entries(&self) -> &VecDeque<TraceEntry> {
+        &self.entries
+    }
+
+    // Removes and returns everything recorded so far, oldest first
+    pub fn take_entries(&mut self) -> Vec<TraceEntry> {
+        self.entries.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+
+    fn entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            pc,
+            opcode: 0x00E0,
+            changes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn trace_ring_buffer() {
+        let mut tracer = Tracer::new(3);
+        for pc in 0..5 {
+            tracer.record(entry(pc));
+        }
+        let pcs: Vec<u16> = tracer.get_entries().iter().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, vec![2, 3, 4]);
+        assert_eq!(tracer.take_entries().len(), 3);
+        assert!(tracer.get_entries().is_empty());
+    }
+
+    #[test]
+    fn trace_filter() {
+        let mut filter = TraceFilter::default();
+        assert!(filter.matches(0x200, 0x6A02));
+
+        filter.address_range = Some((0x300, 0x3FF));
+        assert!(!filter.matches(0x200, 0x6A02));
+        assert!(filter.matches(0x300, 0x6A02));
+        assert!(filter.matches(0x3FF, 0x6A02));
+
+        filter.classes = vec![OpcodeClass::Display];
+        assert!(!filter.matches(0x300, 0x6A02));
+        assert!(filter.matches(0x300, 0xD125));
+    }
+
+    #[test]
+    fn trace_entry_text() {
+        let mut before = RegisterSnapshot {
+            v: [0; REGISTER_COUNT],
+            i: 0x200,
+            delay_timer: 0,
+            sound_timer: 0,
+        };
+        let mut after = before;
+        after.v[0xA] = 2;
+        after.i = 0x22A;
+        let entry = TraceEntry {
+            pc: 0x202,
+            opcode: 0x6A02,
+            changes: before.get_changes(&after),
+        };
+        assert_eq!(entry.to_string(), "0202  6A02  LD VA, 0x02        VA=00->02 I=200->22A");
+
+        before = after;
+        let entry = TraceEntry {
+            pc: 0x20A,
+            opcode: 0xDAB6,
+            changes: before.get_changes(&after),
+        };
+        assert_eq!(entry.to_string(), "020A  DAB6  DRW VA, VB, 6");
+
+        let symbols = SymbolTable::parse("20A draw_ball\n2EA ball").unwrap();
+        let entry = TraceEntry {
+            pc: 0x20A,
+            opcode: 0xA2EA,
+            changes: vec![RegisterChange {
+                register: Register::I,
+                before: 0x200,
+                after: 0x2EA,
+            }],
+        };
+        assert_eq!(entry.to_string_with_symbols(&symbols), "020A  A2EA  LD I, ball         I=200->2EA  ; draw_ball");
+    }
+}
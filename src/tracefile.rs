@@ -0,0 +1,310 @@
+// A line based format for recording the full machine state after every instruction, so that two
+// runs of a ROM, for example under different quirk presets, can be compared to find where they
+// stopped agreeing with each other.
+//
+// The file starts with a header line, followed by one line per instruction:
+//     0208 A2EA V=020C3F0C000000000000000000000000 I=2EA DT=00 ST=00 S= M=1B7A3C08 D=5E0F92D4
+// which is the address and opcode of the instruction, then the registers once it has run. S is the
+// stack, as a comma separated list of return addresses. M and D are CRC-32 checksums of the whole
+// of memory and of the display, so a store or a draw that went differently is caught on the line
+// it happened rather than when something later reads it back.
+
+use super::cpu::{CPU, REGISTER_COUNT};
+use super::display::Display;
+use super::memory::Memory;
+use super::png::crc32;
+use std::error;
+use std::fmt;
+
+pub const HEADER: &str = "# chip8 trace 2";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; REGISTER_COUNT],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: Vec<u16>,
+    pub memory_checksum: u32,
+    pub display_checksum: u32,
+}
+
+impl TraceRecord {
+    // Should be called once the instruction at pc has been executed
+    pub fn new(pc: u16, opcode: u16, cpu: &CPU, memory: &Memory, display: &Display) -> Self {
+        let mut v = [0; REGISTER_COUNT];
+        v.copy_from_slice(cpu.get_v_registers());
+        TraceRecord {
+            pc,
+            opcode,
+            v,
+            i: cpu.get_i(),
+            delay_timer: cpu.get_delay_timer(),
+            sound_timer: cpu.get_sound_timer(),
+            stack: cpu.get_stack().to_vec(),
+            memory_checksum: crc32(&memory.memory),
+            display_checksum: crc32(&display.memory),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (pc, opcode, registers, i, delay_timer, sound_timer, stack, memory_checksum, display_checksum) = match fields.as_slice() {
+            [pc, opcode, registers, i, delay_timer, sound_timer, stack, memory_checksum, display_checksum] => {
+                (pc, opcode, registers, i, delay_timer, sound_timer, stack, memory_checksum, display_checksum)
+            }
+            _ => return None,
+        };
+
+        let registers = registers.strip_prefix("V=")?;
+        if registers.len() != REGISTER_COUNT * 2 {
+            return None;
+        }
+        let mut v = [0; REGISTER_COUNT];
+        for (index, register) in v.iter_mut().enumerate() {
+            *register = u8::from_str_radix(registers.get(index * 2..index * 2 + 2)?, 16).ok()?;
+        }
+
+        let stack = stack.strip_prefix("S=")?;
+        let stack = if stack.is_empty() {
+            Vec::new()
+        } else {
+            stack.split(',').map(|address| u16::from_str_radix(address, 16).ok()).collect::<Option<Vec<u16>>>()?
+        };
+
+        Some(TraceRecord {
+            pc: u16::from_str_radix(pc, 16).ok()?,
+            opcode: u16::from_str_radix(opcode, 16).ok()?,
+            v,
+            i: u16::from_str_radix(i.strip_prefix("I=")?, 16).ok()?,
+            delay_timer: u8::from_str_radix(delay_timer.strip_prefix("DT=")?, 16).ok()?,
+            sound_timer: u8::from_str_radix(sound_timer.strip_prefix("ST=")?, 16).ok()?,
+            stack,
+            memory_checksum: u32::from_str_radix(memory_checksum.strip_prefix("M=")?, 16).ok()?,
+            display_checksum: u32::from_str_radix(display_checksum.strip_prefix("D=")?, 16).ok()?,
+        })
+    }
+
+    // Describes every field that differs between the two records, or nothing if they match
+    pub fn compare(&self, other: &TraceRecord) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.pc != other.pc {
+            differences.push(format!("pc {:04X} != {:04X}", self.pc, other.pc));
+        }
+        if self.opcode != other.opcode {
+            differences.push(format!("opcode {:04X} != {:04X}", self.opcode, other.opcode));
+        }
+        for (index, (a, b)) in self.v.iter().zip(other.v.iter()).enumerate() {
+            if a != b {
+                differences.push(format!("V{:X} {:02X} != {:02X}", index, a, b));
+            }
+        }
+        if self.i != other.i {
+            differences.push(format!("I {:03X} != {:03X}", self.i, other.i));
+        }
+        if self.delay_timer != other.delay_timer {
+            differences.push(format!("DT {:02X} != {:02X}", self.delay_timer, other.delay_timer));
+        }
+        if self.sound_timer != other.sound_timer {
+            differences.push(format!("ST {:02X} != {:02X}", self.sound_timer, other.sound_timer));
+        }
+        if self.stack != other.stack {
+            differences.push(format!("stack {} != {}", format_stack(&self.stack), format_stack(&other.stack)));
+        }
+        if self.memory_checksum != other.memory_checksum {
+            differences.push(format!("memory {:08X} != {:08X}", self.memory_checksum, other.memory_checksum));
+        }
+        if self.display_checksum != other.display_checksum {
+            differences.push(format!("display {:08X} != {:08X}", self.display_checksum, other.display_checksum));
+        }
+        differences
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X} {:04X} V=", self.pc, self.opcode)?;
+        for register in self.v.iter() {
+            write!(f, "{:02X}", register)?;
+        }
+        write!(
+            f,
+            " I={:03X} DT={:02X} ST={:02X} S={} M={:08X} D={:08X}",
+            self.i,
+            self.delay_timer,
+            self.sound_timer,
+            format_stack(&self.stack),
+            self.memory_checksum,
+            self.display_checksum
+        )
+    }
+}
+
+fn format_stack(stack: &[u16]) -> String {
+    stack.iter().map(|address| format!("{:03X}", address)).collect::<Vec<String>>().join(",")
+}
+
+pub fn parse_trace(text: &str) -> Result<Vec<TraceRecord>, TraceFileError> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim_end) != Some(HEADER) {
+        return Err(TraceFileError::MissingHeader);
+    }
+
+    let mut records = Vec::new();
+    for (index, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match TraceRecord::parse(line) {
+            Some(record) => records.push(record),
+            //the header is line 1
+            None => return Err(TraceFileError::InvalidLine(index + 2)),
+        }
+    }
+    Ok(records)
+}
+
+// The first place two traces disagree. When one trace is a prefix of the other, the divergence is
+// where the shorter one ends and there are no differences to describe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub differences: Vec<String>,
+}
+
+pub fn find_divergence(a: &[TraceRecord], b: &[TraceRecord]) -> Option<Divergence> {
+    for (index, (record_a, record_b)) in a.iter().zip(b.iter()).enumerate() {
+        let differences = record_a.compare(record_b);
+        if !differences.is_empty() {
+            return Some(Divergence { index, differences });
+        }
+    }
+    if a.len() != b.len() {
+        return Some(Divergence {
+            index: a.len().min(b.len()),
+            differences: Vec::new(),
+        });
+    }
+    None
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TraceFileError {
+    MissingHeader,
+    InvalidLine(usize),
+}
+
+impl fmt::Display for TraceFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceFileError::MissingHeader => write!(f, "Not a trace file, the first line should be '{}'", HEADER),
+            TraceFileError::InvalidLine(line) => write!(f, "Invalid trace record on line {}", line),
+        }
+    }
+}
+
+impl error::Error for TraceFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tracefile_tests {
+    use super::*;
+
+    fn record(pc: u16) -> TraceRecord {
+        TraceRecord {
+            pc,
+            opcode: 0x2300,
+            v: [0; REGISTER_COUNT],
+            i: 0x2EA,
+            delay_timer: 0x3C,
+            sound_timer: 0,
+            stack: vec![0x200, 0x2A4],
+            memory_checksum: 0x1B7A_3C08,
+            display_checksum: 0x5E0F_92D4,
+        }
+    }
+
+    #[test]
+    fn tracefile_round_trip() {
+        let mut record = record(0x202);
+        record.v[0xA] = 0x12;
+        let line = record.to_string();
+        assert_eq!(line, "0202 2300 V=00000000000000000000120000000000 I=2EA DT=3C ST=00 S=200,2A4 M=1B7A3C08 D=5E0F92D4");
+        assert_eq!(TraceRecord::parse(&line), Some(record.clone()));
+
+        record.stack.clear();
+        let line = record.to_string();
+        assert!(line.contains(" S= "));
+        assert_eq!(TraceRecord::parse(&line), Some(record));
+    }
+
+    #[test]
+    fn tracefile_checksums() {
+        let cpu = CPU::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let before = TraceRecord::new(0x200, 0x00E0, &cpu, &memory, &display);
+
+        memory.memory[0x300] = 0xAA;
+        let after = TraceRecord::new(0x200, 0x00E0, &cpu, &memory, &display);
+        assert_ne!(after.memory_checksum, before.memory_checksum);
+        assert_eq!(after.display_checksum, before.display_checksum);
+
+        display.draw(&[0x80], (0, 0));
+        let drawn = TraceRecord::new(0x200, 0x00E0, &cpu, &memory, &display);
+        assert_eq!(drawn.memory_checksum, after.memory_checksum);
+        assert_ne!(drawn.display_checksum, after.display_checksum);
+    }
+
+    #[test]
+    fn tracefile_parse_trace() {
+        let text = format!("{}\n{}\n\n{}\n", HEADER, record(0x200), record(0x202));
+        assert_eq!(parse_trace(&text), Ok(vec![record(0x200), record(0x202)]));
+
+        assert_eq!(parse_trace("0200 6A02"), Err(TraceFileError::MissingHeader));
+        let text = format!("{}\n{}\n0202 6A02 V=00\n", HEADER, record(0x200));
+        assert_eq!(parse_trace(&text), Err(TraceFileError::InvalidLine(3)));
+    }
+
+    #[test]
+    fn tracefile_find_divergence() {
+        let a = vec![record(0x200), record(0x202), record(0x204)];
+        let mut b = a.clone();
+        assert_eq!(find_divergence(&a, &b), None);
+
+        b[1].v[3] = 1;
+        b[1].i = 0x300;
+        assert_eq!(
+            find_divergence(&a, &b),
+            Some(Divergence {
+                index: 1,
+                differences: vec![String::from("V3 00 != 01"), String::from("I 2EA != 300")],
+            })
+        );
+
+        //a write to memory or the display shows up on the line it happened, not when it's read back
+        let mut c = a.clone();
+        c[2].memory_checksum = 0;
+        c[2].display_checksum = 0x5E0F_92D5;
+        assert_eq!(
+            find_divergence(&a, &c),
+            Some(Divergence {
+                index: 2,
+                differences: vec![String::from("memory 1B7A3C08 != 00000000"), String::from("display 5E0F92D4 != 5E0F92D5")],
+            })
+        );
+
+        assert_eq!(
+            find_divergence(&a, &a[..2]),
+            Some(Divergence {
+                index: 2,
+                differences: Vec::new(),
+            })
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Checks the result layouts in src/conformance.rs against the real test suite ROMs, which aren't
+// bundled with the crate. Download them into a directory, then run:
+//     CHIP8_TEST_SUITE=<directory> cargo test --test conformance -- --ignored
+//
+// Each ROM is run under a preset that passes all of its tests, so every cell has to read back as a
+// pass. When one doesn't, the screen is printed, to show where the ROM actually drew its results.
+
+use chip8::conformance::{self, TestOutcome};
+use chip8::display::{COLUMNS, ROWS};
+use chip8::quirks::QuirkPreset;
+use chip8::Chip8;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn display_as_text(chip8: &Chip8) -> String {
+    let memory = &chip8.get_display().memory;
+    let mut text = String::new();
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            text.push(if memory[(row * COLUMNS) + column] != 0 { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// The presets each ROM is expected to pass every test under
+fn get_passing_presets(rom_name: &str) -> &'static [QuirkPreset] {
+    match rom_name {
+        //the quirks test checks the platform it is told it's running on, which the modern preset doesn't match
+        "quirks" => &[QuirkPreset::CosmacVip, QuirkPreset::Schip, QuirkPreset::XoChip],
+        _ => &[QuirkPreset::Modern],
+    }
+}
+
+#[test]
+#[ignore]
+fn conformance_layouts_match_real_roms() {
+    let directory = env::var("CHIP8_TEST_SUITE").expect("CHIP8_TEST_SUITE should name the directory holding the test suite ROMs");
+    let mut checked = 0;
+    for rom in conformance::get_test_suite() {
+        let program = match fs::read(Path::new(&directory).join(rom.file_name)) {
+            Ok(program) => program,
+            Err(_) => continue,
+        };
+        for preset in get_passing_presets(rom.name) {
+            let chip8 = conformance::run_rom_to_end(&rom, &program, *preset).unwrap();
+            let results = conformance::run_rom(&rom, &program, *preset).unwrap();
+            let failed: Vec<String> = results
+                .iter()
+                .filter(|result| result.outcome != TestOutcome::Pass)
+                .map(|result| format!("{} ({})", result.name, result.outcome))
+                .collect();
+            assert!(
+                failed.is_empty(),
+                "{} under {} didn't read back as passing: {}\n{}",
+                rom.name,
+                preset.get_name(),
+                failed.join(", "),
+                display_as_text(&chip8)
+            );
+        }
+        checked += 1;
+    }
+    assert!(checked > 0, "None of the test suite ROMs were found in {}", directory);
+}
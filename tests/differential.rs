@@ -0,0 +1,158 @@
+// Runs random instruction sequences on both the CPU and the simple reference interpreter in
+// tests/reference, and compares the whole machine state after every step.
+
+mod reference;
+
+use chip8::cpu::CPU;
+use chip8::display::Display;
+use chip8::keyboard::Keyboard;
+use chip8::memory::Memory;
+use chip8::quirks::{AddressOverflow, QuirkPreset, Quirks};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reference::Reference;
+
+const SEQUENCES: u64 = 300;
+const STEPS_PER_SEQUENCE: usize = 200;
+
+struct Machine {
+    cpu: CPU,
+    display: Display,
+    memory: Memory,
+    keyboard: Keyboard,
+}
+
+// Picks a random instruction, with every valid encoding equally likely to be chosen
+fn random_opcode(rng: &mut StdRng) -> u16 {
+    let x: u16 = rng.gen_range(0, 16);
+    let y: u16 = rng.gen_range(0, 16);
+    let n: u16 = rng.gen_range(0, 16);
+    let kk: u16 = rng.gen_range(0, 256);
+    let nnn: u16 = rng.gen_range(0, 0x1000);
+    let arithmetic = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE];
+    let misc = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+
+    match rng.gen_range(0, 18) {
+        0 => 0x00E0,
+        1 => 0x00EE,
+        2 => 0x1000 | nnn,
+        3 => 0x2000 | nnn,
+        4 => 0x3000 | (x << 8) | kk,
+        5 => 0x4000 | (x << 8) | kk,
+        6 => 0x5000 | (x << 8) | (y << 4),
+        7 => 0x6000 | (x << 8) | kk,
+        8 => 0x7000 | (x << 8) | kk,
+        9 => 0x8000 | (x << 8) | (y << 4) | arithmetic[rng.gen_range(0, arithmetic.len())],
+        10 => 0x9000 | (x << 8) | (y << 4),
+        11 => 0xA000 | nnn,
+        12 => 0xB000 | nnn,
+        13 => 0xC000 | (x << 8) | kk,
+        14 => 0xD000 | (x << 8) | (y << 4) | n,
+        15 => 0xE09E | (x << 8),
+        16 => 0xE0A1 | (x << 8),
+        _ => 0xF000 | (x << 8) | misc[rng.gen_range(0, misc.len())],
+    }
+}
+
+fn compare(machine: &Machine, reference: &Reference, history: &[u16]) {
+    let context = || format!("after {:04X?}", history);
+    assert_eq!(machine.cpu.get_pc(), reference.pc, "pc differs {}", context());
+    assert_eq!(machine.cpu.get_v_registers(), &reference.v[..], "registers differ {}", context());
+    assert_eq!(machine.cpu.get_i(), reference.i, "I differs {}", context());
+    assert_eq!(machine.cpu.get_delay_timer(), reference.delay_timer, "delay timer differs {}", context());
+    assert_eq!(machine.cpu.get_sound_timer(), reference.sound_timer, "sound timer differs {}", context());
+    assert_eq!(machine.cpu.get_stack(), &reference.stack[..], "stack differs {}", context());
+    assert!(machine.memory.memory[..] == reference.memory[..], "memory differs {}", context());
+    assert!(machine.display.memory[..] == reference.display[..], "display differs {}", context());
+}
+
+fn run_sequence(quirks: Quirks, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut machine = Machine {
+        cpu: CPU::new(),
+        display: Display::new(),
+        memory: Memory::new(),
+        keyboard: Keyboard::new(),
+    };
+    machine.cpu.set_quirks(quirks);
+    machine.cpu.seed_rng(seed);
+    let mut reference = Reference::new(quirks, seed);
+
+    //start from the same random memory, so that sprites, BCD and loads have something to work with
+    for address in 0x200..reference::MEMORY_SIZE {
+        let byte = rng.gen();
+        machine.memory.memory[address] = byte;
+        reference.memory[address] = byte;
+    }
+
+    let mut history = Vec::new();
+    for _ in 0..STEPS_PER_SEQUENCE {
+        match rng.gen_range(0, 20) {
+            0 => {
+                let key = rng.gen_range(0, 16);
+                machine.keyboard.press_key(key);
+                reference.key = Some(key);
+            }
+            1 => {
+                machine.keyboard.release_key();
+                reference.key = None;
+            }
+            2 => {
+                machine.cpu.decrement_timers();
+                reference.tick();
+            }
+            _ => {}
+        }
+
+        let opcode = random_opcode(&mut rng);
+        history.push(opcode);
+        let result = machine.cpu.process_opcode(opcode, &mut machine.display, &mut machine.memory, &machine.keyboard);
+        let reference_succeeded = reference.execute(opcode);
+        assert_eq!(result.is_ok(), reference_succeeded, "{:?} vs {} after {:04X?}", result, reference_succeeded, history);
+        if !reference_succeeded {
+            //the state after an error isn't specified, so there's nothing more to compare
+            return;
+        }
+        compare(&machine, &reference, &history);
+    }
+}
+
+fn run_sequences(quirks: Quirks) {
+    for seed in 0..SEQUENCES {
+        run_sequence(quirks, seed);
+    }
+}
+
+#[test]
+fn differential_modern() {
+    run_sequences(Quirks::new(QuirkPreset::Modern));
+}
+
+#[test]
+fn differential_cosmac_vip() {
+    run_sequences(Quirks::new(QuirkPreset::CosmacVip));
+}
+
+#[test]
+fn differential_schip() {
+    run_sequences(Quirks::new(QuirkPreset::Schip));
+}
+
+#[test]
+fn differential_xo_chip() {
+    run_sequences(Quirks::new(QuirkPreset::XoChip));
+}
+
+#[test]
+fn differential_flag_written_first() {
+    let mut quirks = Quirks::new(QuirkPreset::Modern);
+    quirks.flag_written_first = true;
+    run_sequences(quirks);
+}
+
+#[test]
+fn differential_address_overflow_errors() {
+    let mut quirks = Quirks::new(QuirkPreset::Modern);
+    quirks.address_overflow = AddressOverflow::Error;
+    run_sequences(quirks);
+}
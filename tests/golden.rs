@@ -0,0 +1,214 @@
+// Runs every ROM in web/roms for a fixed number of frames, with a scripted set of key presses and a
+// fixed random seed, and compares the final display against a reference image in tests/golden. The
+// references are the display's pixels as text, one character per pixel, so they can be diffed.
+//
+// To regenerate the reference images after an intentional change in behaviour, run:
+//     UPDATE_GOLDEN_IMAGES=1 cargo test --test golden
+
+use chip8::display::{COLUMNS, ROWS};
+use chip8::Chip8;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const SEED: u64 = 0xC8;
+const FRAMES: usize = 600; //10 seconds at 60Hz
+const CYCLES_PER_FRAME: usize = 8; //the same ~480Hz clock speed the web front end uses
+
+enum Input {
+    Press(u8),
+    Release(u8),
+}
+
+use Input::*;
+
+fn get_path(directory: &str, file: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), directory, file].iter().collect()
+}
+
+fn display_as_text(chip8: &Chip8) -> String {
+    let memory = &chip8.get_display().memory;
+    let mut text = String::new();
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            text.push(if memory[(row * COLUMNS) + column] != 0 { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// Each input is applied at the start of the given frame
+fn run_golden_test(rom_name: &str, inputs: &[(usize, Input)]) {
+    let rom = fs::read(get_path("web/roms", rom_name)).unwrap();
+    let mut chip8 = Chip8::power_up();
+    chip8.seed_rng(SEED);
+    chip8.try_load_rom(&rom).unwrap();
+
+    for frame in 0..FRAMES {
+        for (_, input) in inputs.iter().filter(|(input_frame, _)| *input_frame == frame) {
+            match input {
+                Press(key) => chip8.press_key(*key),
+                Release(key) => chip8.release_key(*key),
+            }
+        }
+        for _ in 0..CYCLES_PER_FRAME {
+            if let Err(e) = chip8.step() {
+                panic!("{} failed on frame {} at {:#05X}: {}", rom_name, frame, chip8.get_pc(), e);
+            }
+        }
+        chip8.decrement_timers();
+    }
+
+    let actual = display_as_text(&chip8);
+    let golden_path = get_path("tests/golden", &format!("{}.txt", rom_name));
+    if env::var_os("UPDATE_GOLDEN_IMAGES").is_some() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&golden_path).unwrap_or_else(|_| panic!("No reference image at {}", golden_path.display()));
+    if actual != expected {
+        let failure_path = get_path("target/golden-failures", &format!("{}.png", rom_name));
+        fs::create_dir_all(failure_path.parent().unwrap()).unwrap();
+        fs::write(&failure_path, chip8.take_screenshot()).unwrap();
+        panic!(
+            "{} does not match its reference image, the actual display was written to {}:\n{}",
+            rom_name,
+            failure_path.display(),
+            actual
+        );
+    }
+}
+
+#[test]
+fn golden_15puzzle() {
+    run_golden_test("15PUZZLE", &[(60, Press(0x2)), (70, Release(0x2)), (120, Press(0x4)), (130, Release(0x4))]);
+}
+
+#[test]
+fn golden_blinky() {
+    run_golden_test("BLINKY", &[(120, Press(0x8)), (200, Release(0x8)), (240, Press(0x3)), (320, Release(0x3))]);
+}
+
+#[test]
+fn golden_blitz() {
+    run_golden_test("BLITZ", &[(240, Press(0x5)), (250, Release(0x5))]);
+}
+
+#[test]
+fn golden_brix() {
+    run_golden_test("BRIX", &[(60, Press(0x4)), (120, Release(0x4)), (180, Press(0x6)), (300, Release(0x6))]);
+}
+
+#[test]
+fn golden_connect4() {
+    run_golden_test("CONNECT4", &[(60, Press(0x6)), (70, Release(0x6)), (120, Press(0x5)), (130, Release(0x5))]);
+}
+
+#[test]
+fn golden_guess() {
+    run_golden_test("GUESS", &[(120, Press(0x5)), (130, Release(0x5)), (300, Press(0x5)), (310, Release(0x5))]);
+}
+
+#[test]
+fn golden_hidden() {
+    run_golden_test("HIDDEN", &[(300, Press(0x5)), (310, Release(0x5)), (400, Press(0x6)), (410, Release(0x6))]);
+}
+
+#[test]
+fn golden_ibm() {
+    run_golden_test("IBM", &[]);
+}
+
+#[test]
+fn golden_invaders() {
+    run_golden_test("INVADERS", &[(240, Press(0x5)), (250, Release(0x5)), (360, Press(0x6)), (400, Release(0x6))]);
+}
+
+#[test]
+fn golden_kaleid() {
+    run_golden_test("KALEID", &[(30, Press(0x6)), (60, Release(0x6)), (90, Press(0x2)), (120, Release(0x2))]);
+}
+
+#[test]
+fn golden_maze() {
+    run_golden_test("MAZE", &[]);
+}
+
+#[test]
+fn golden_merlin() {
+    run_golden_test("MERLIN", &[(400, Press(0x4)), (410, Release(0x4))]);
+}
+
+#[test]
+fn golden_missile() {
+    run_golden_test("MISSILE", &[(120, Press(0x8)), (130, Release(0x8)), (300, Press(0x8)), (310, Release(0x8))]);
+}
+
+#[test]
+fn golden_pong() {
+    run_golden_test("PONG", &[(60, Press(0x1)), (120, Release(0x1)), (180, Press(0xD)), (240, Release(0xD))]);
+}
+
+#[test]
+fn golden_pong2() {
+    run_golden_test("PONG2", &[(60, Press(0x4)), (120, Release(0x4)), (180, Press(0xC)), (240, Release(0xC))]);
+}
+
+#[test]
+fn golden_puzzle() {
+    run_golden_test("PUZZLE", &[(300, Press(0x6)), (310, Release(0x6))]);
+}
+
+#[test]
+fn golden_syzygy() {
+    //the snake moves on its own once F starts a game, so start late and steer it up and right towards the target before it reaches a wall
+    run_golden_test(
+        "SYZYGY",
+        &[
+            (430, Press(0xF)),
+            (440, Release(0xF)),
+            (450, Press(0x3)),
+            (455, Release(0x3)),
+            (495, Press(0x8)),
+            (500, Release(0x8)),
+        ],
+    );
+}
+
+#[test]
+fn golden_tank() {
+    run_golden_test("TANK", &[(60, Press(0x6)), (120, Release(0x6)), (180, Press(0x5)), (190, Release(0x5))]);
+}
+
+#[test]
+fn golden_tetris() {
+    run_golden_test("TETRIS", &[(60, Press(0x4)), (70, Release(0x4)), (120, Press(0x6)), (140, Release(0x6))]);
+}
+
+#[test]
+fn golden_tictac() {
+    run_golden_test("TICTAC", &[(60, Press(0x5)), (70, Release(0x5)), (200, Press(0x1)), (210, Release(0x1))]);
+}
+
+#[test]
+fn golden_ufo() {
+    run_golden_test("UFO", &[(120, Press(0x5)), (130, Release(0x5)), (300, Press(0x4)), (310, Release(0x4))]);
+}
+
+#[test]
+fn golden_vbrix() {
+    run_golden_test("VBRIX", &[(120, Press(0x7)), (130, Release(0x7)), (200, Press(0x1)), (260, Release(0x1))]);
+}
+
+#[test]
+fn golden_vers() {
+    run_golden_test("VERS", &[(120, Press(0x7)), (130, Release(0x7)), (300, Press(0x1)), (310, Release(0x1))]);
+}
+
+#[test]
+fn golden_wipeoff() {
+    run_golden_test("WIPEOFF", &[(60, Press(0x4)), (120, Release(0x4)), (180, Press(0x6)), (240, Release(0x6))]);
+}
@@ -0,0 +1,137 @@
+// Property tests that feed arbitrary opcodes and memory images to the interpreter. Whatever a ROM
+// contains, the core should only ever return a CPUError, and never panic.
+
+use chip8::cpu::CPU;
+use chip8::display::Display;
+use chip8::keyboard::Keyboard;
+use chip8::memory::{Memory, MEMORY_SIZE};
+use chip8::quirks::{QuirkPreset, Quirks, QUIRK_PRESETS};
+use chip8::Chip8;
+use proptest::prelude::*;
+
+#[derive(Clone, Debug)]
+enum Event {
+    Execute(u16),
+    //the same instruction over and over, like a tight loop in a ROM
+    Repeat(u16, usize),
+    Press(u8),
+    Release,
+    Tick,
+}
+
+// Mostly well formed instructions, since a completely random opcode is rarely a valid FX instruction
+fn opcode() -> impl Strategy<Value = u16> {
+    let misc = vec![0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+    let arithmetic = vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE];
+    prop_oneof![
+        any::<u16>(),
+        (0..16u16, prop::sample::select(misc)).prop_map(|(x, low)| 0xF000 | (x << 8) | low),
+        (0..16u16, 0..16u16, prop::sample::select(arithmetic)).prop_map(|(x, y, low)| 0x8000 | (x << 8) | (y << 4) | low),
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0x6000 | (x << 8) | kk as u16),
+        Just(0x00EE),
+    ]
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    prop_oneof![
+        8 => opcode().prop_map(Event::Execute),
+        1 => (opcode(), 1..1000usize).prop_map(|(opcode, count)| Event::Repeat(opcode, count)),
+        1 => (0..16u8).prop_map(Event::Press),
+        1 => Just(Event::Release),
+        1 => Just(Event::Tick),
+    ]
+}
+
+fn preset() -> impl Strategy<Value = QuirkPreset> {
+    prop::sample::select(QUIRK_PRESETS.to_vec())
+}
+
+proptest! {
+    // Opcodes are executed directly, so the pc keeps running past the end of memory and
+    // every instruction gets to see whatever state the ones before it left behind
+    #[test]
+    fn arbitrary_opcodes_never_panic(preset in preset(), events in prop::collection::vec(event(), 1..500)) {
+        let mut cpu = CPU::new();
+        let mut display = Display::new();
+        let mut memory = Memory::new();
+        let mut keyboard = Keyboard::new();
+        cpu.set_quirks(Quirks::new(preset));
+        cpu.seed_rng(0);
+
+        for event in events {
+            match event {
+                Event::Execute(opcode) => {
+                    let _ = cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard);
+                }
+                Event::Repeat(opcode, count) => {
+                    for _ in 0..count {
+                        let _ = cpu.process_opcode(opcode, &mut display, &mut memory, &keyboard);
+                    }
+                }
+                Event::Press(key) => keyboard.press_key(key),
+                Event::Release => keyboard.release_key(),
+                Event::Tick => cpu.decrement_timers(),
+            }
+        }
+    }
+
+    // Runs a whole machine over an arbitrary memory image, stopping at the first error
+    #[test]
+    fn arbitrary_memory_never_panics(preset in preset(), image in prop::collection::vec(any::<u8>(), MEMORY_SIZE), steps in 1..5000usize) {
+        let mut chip8 = Chip8::power_up();
+        chip8.set_quirks(Quirks::new(preset));
+        chip8.seed_rng(0);
+        chip8.get_memory_mut().memory.copy_from_slice(&image);
+
+        for step in 0..steps {
+            if chip8.step().is_err() {
+                break;
+            }
+            if step % 8 == 7 {
+                chip8.decrement_timers();
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_roms_never_panic(rom in prop::collection::vec(any::<u8>(), 0..(MEMORY_SIZE + 64))) {
+        let mut chip8 = Chip8::power_up();
+        if chip8.try_load_rom(&rom).is_ok() {
+            for _ in 0..1000 {
+                if chip8.step().is_err() {
+                    break;
+                }
+            }
+        } else {
+            prop_assert!(rom.len() > MEMORY_SIZE - 0x200);
+        }
+    }
+}
+
+// Regressions found by the tests above
+#[test]
+fn properties_add_i_wraps() {
+    let mut cpu = CPU::new();
+    let mut display = Display::new();
+    let mut memory = Memory::new();
+    let keyboard = Keyboard::new();
+
+    cpu.process_opcode(0x60FF, &mut display, &mut memory, &keyboard).unwrap();
+    for _ in 0..300 {
+        cpu.process_opcode(0xF01E, &mut display, &mut memory, &keyboard).unwrap();
+    }
+    assert_eq!(cpu.get_i(), ((300 * 0xFF) % MEMORY_SIZE) as u16);
+}
+
+#[test]
+fn properties_pc_wraps() {
+    let mut cpu = CPU::new();
+    let mut display = Display::new();
+    let mut memory = Memory::new();
+    let keyboard = Keyboard::new();
+
+    for _ in 0..0x8000 {
+        cpu.process_opcode(0x6000, &mut display, &mut memory, &keyboard).unwrap();
+    }
+    assert_eq!(cpu.get_pc(), 0x200);
+}
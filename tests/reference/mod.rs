@@ -0,0 +1,255 @@
+// A deliberately simple CHIP-8 interpreter, written straight from the instruction descriptions rather
+// than sharing code with the crate, which the differential tests compare the real CPU against. Speed
+// and structure don't matter here, only that every instruction is easy to check by reading it.
+
+use chip8::font::BIG_FONT_CHARACTERS;
+use chip8::memory::FONT_CHARACTERS;
+use chip8::quirks::{AddressOverflow, Quirks};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const MEMORY_SIZE: usize = 4096;
+
+pub struct Reference {
+    pub pc: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    //holds the address of each CALL instruction, the same as the crate does
+    pub stack: Vec<u16>,
+    pub memory: Vec<u8>,
+    pub display: Vec<u8>,
+    pub key: Option<u8>,
+    quirks: Quirks,
+    vblank: bool,
+    rng: StdRng,
+}
+
+impl Reference {
+    pub fn new(quirks: Quirks, seed: u64) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[..FONT_CHARACTERS.len()].copy_from_slice(&FONT_CHARACTERS);
+        memory[FONT_CHARACTERS.len()..FONT_CHARACTERS.len() + BIG_FONT_CHARACTERS.len()].copy_from_slice(&BIG_FONT_CHARACTERS);
+        Reference {
+            pc: 0x200,
+            v: [0; 16],
+            i: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: Vec::new(),
+            memory,
+            display: vec![0; WIDTH * HEIGHT],
+            key: None,
+            quirks,
+            vblank: true,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.vblank = true;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Addresses are 12 bits, so anything larger either wraps or is an error
+    fn address(&self, value: usize) -> Option<u16> {
+        match (value < MEMORY_SIZE, self.quirks.address_overflow) {
+            (true, _) => Some(value as u16),
+            (false, AddressOverflow::Wrap) => Some((value % MEMORY_SIZE) as u16),
+            (false, AddressOverflow::Error) => None,
+        }
+    }
+
+    fn jump(&mut self, address: usize) -> bool {
+        match self.address(address) {
+            Some(address) => {
+                self.pc = address;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns false wherever the crate is expected to return an error
+    pub fn execute(&mut self, opcode: u16) -> bool {
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let kk = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let next = self.pc as usize + 2;
+        let skip = self.pc as usize + 4;
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => {
+                for pixel in self.display.iter_mut() {
+                    *pixel = 0;
+                }
+                return self.jump(next);
+            }
+            0x0 if opcode == 0x00EE => match self.stack.pop() {
+                Some(address) => return self.jump(address as usize + 2),
+                None => return false,
+            },
+            0x1 => self.pc = nnn,
+            0x2 => {
+                if self.stack.len() == self.quirks.stack_depth {
+                    return false;
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            0x3 => return self.jump(if self.v[x] == kk { skip } else { next }),
+            0x4 => return self.jump(if self.v[x] != kk { skip } else { next }),
+            0x5 if n == 0 => return self.jump(if self.v[x] == self.v[y] { skip } else { next }),
+            0x6 => {
+                self.v[x] = kk;
+                return self.jump(next);
+            }
+            0x7 => {
+                self.v[x] = ((self.v[x] as u16 + kk as u16) % 256) as u8;
+                return self.jump(next);
+            }
+            0x8 => {
+                let vx = self.v[x] as u16;
+                let vy = self.v[y] as u16;
+                let shifted = if self.quirks.shift_uses_vy { vy } else { vx };
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x2 => (vx & vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x3 => (vx ^ vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x4 => ((vx + vy) % 256, Some(if vx + vy > 255 { 1 } else { 0 })),
+                    0x5 => ((vx + 256 - vy) % 256, Some(if vx >= vy { 1 } else { 0 })),
+                    0x6 => (shifted / 2, Some(shifted % 2)),
+                    0x7 => ((vy + 256 - vx) % 256, Some(if vy >= vx { 1 } else { 0 })),
+                    0xE => ((shifted * 2) % 256, Some(shifted / 128)),
+                    _ => return false,
+                };
+                match flag {
+                    Some(flag) if self.quirks.flag_written_first => {
+                        self.v[0xF] = flag as u8;
+                        self.v[x] = result as u8;
+                    }
+                    Some(flag) => {
+                        self.v[x] = result as u8;
+                        self.v[0xF] = flag as u8;
+                    }
+                    None => self.v[x] = result as u8,
+                }
+                return self.jump(next);
+            }
+            0x9 if n == 0 => return self.jump(if self.v[x] != self.v[y] { skip } else { next }),
+            0xA => {
+                self.i = nnn;
+                return self.jump(next);
+            }
+            0xB => {
+                let offset = if self.quirks.jump_uses_vx { self.v[x] } else { self.v[0] };
+                return self.jump(nnn as usize + offset as usize);
+            }
+            0xC => {
+                let random: u8 = self.rng.gen();
+                self.v[x] = random & kk;
+                return self.jump(next);
+            }
+            0xD => {
+                if self.quirks.display_wait && !self.vblank {
+                    return true;
+                }
+                self.vblank = false;
+                let mut sprite = Vec::new();
+                for row in 0..n {
+                    match self.address(self.i as usize + row) {
+                        Some(address) => sprite.push(self.memory[address as usize]),
+                        None => return false,
+                    }
+                }
+                //read the coordinates first, in case either of them is VF
+                let left = self.v[x] as usize % WIDTH;
+                let top = self.v[y] as usize % HEIGHT;
+                self.v[0xF] = 0;
+                for (row, byte) in sprite.iter().enumerate() {
+                    for column in 0..8 {
+                        if self.quirks.clip_sprites && (left + column >= WIDTH || top + row >= HEIGHT) {
+                            continue;
+                        }
+                        let index = ((top + row) % HEIGHT) * WIDTH + (left + column) % WIDTH;
+                        let bit = (byte >> (7 - column)) & 1;
+                        if bit == 1 {
+                            if self.display[index] == 1 {
+                                self.v[0xF] = 1;
+                            }
+                            self.display[index] ^= 1;
+                        }
+                    }
+                }
+                return self.jump(next);
+            }
+            0xE if kk == 0x9E => return self.jump(if self.key == Some(self.v[x]) { skip } else { next }),
+            0xE if kk == 0xA1 => return self.jump(if self.key != Some(self.v[x]) { skip } else { next }),
+            0xF => {
+                match kk {
+                    0x07 => self.v[x] = self.delay_timer,
+                    0x0A => match self.key {
+                        Some(key) => self.v[x] = key,
+                        //wait on this instruction until a key is pressed
+                        None => return true,
+                    },
+                    0x15 => self.delay_timer = self.v[x],
+                    0x18 => self.sound_timer = self.v[x],
+                    0x1E => match self.address(self.i as usize + self.v[x] as usize) {
+                        Some(address) => self.i = address,
+                        None => return false,
+                    },
+                    0x29 => self.i = (self.v[x] as u16 & 0xF) * 5,
+                    0x33 => {
+                        let digits = [self.v[x] / 100, (self.v[x] / 10) % 10, self.v[x] % 10];
+                        for (offset, digit) in digits.iter().enumerate() {
+                            match self.address(self.i as usize + offset) {
+                                Some(address) => self.memory[address as usize] = *digit,
+                                None => return false,
+                            }
+                        }
+                    }
+                    0x55 => {
+                        for register in 0..=x {
+                            match self.address(self.i as usize + register) {
+                                Some(address) => self.memory[address as usize] = self.v[register],
+                                None => return false,
+                            }
+                        }
+                        if self.quirks.memory_increment {
+                            match self.address(self.i as usize + x + 1) {
+                                Some(address) => self.i = address,
+                                None => return false,
+                            }
+                        }
+                    }
+                    0x65 => {
+                        for register in 0..=x {
+                            match self.address(self.i as usize + register) {
+                                Some(address) => self.v[register] = self.memory[address as usize],
+                                None => return false,
+                            }
+                        }
+                        if self.quirks.memory_increment {
+                            match self.address(self.i as usize + x + 1) {
+                                Some(address) => self.i = address,
+                                None => return false,
+                            }
+                        }
+                    }
+                    _ => return false,
+                }
+                return self.jump(next);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
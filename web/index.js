@@ -44,8 +44,6 @@ const hex = (value, length = 2) => {
   return padded.substr(padded.length - length);
 };
 
-const inRange = (value, lower, upper) => value >= lower && value <= upper;
-
 /**** EMULATOR ****/
 class Emulator {
   constructor() {
@@ -62,39 +60,24 @@ class Emulator {
     /**** SET UP MAIN MEMORY INTERFACE ****/
     this.memorySize = this.chip8.get_memory_size();
     this.mainMemory = new Uint8Array(memory.buffer, this.chip8.get_memory(), this.memorySize);
+    this.romLength = 0;
 
     /**** SET UP REGISTER INTERFACE ****/
     this.vRegisters = new Uint8Array(memory.buffer, this.chip8.get_v_registers(), 16);
   }
 
   updateDisplay() {
-    const imageData = this.ctx.createImageData(this.displayWidth, this.displayHeight);
-    for (let i = 0; i < this.displayMemory.length; i++) {
-      imageData.data[i * 4] = this.displayMemory[i] === 1 ? 0xff : 0;
-      imageData.data[i * 4 + 1] = this.displayMemory[i] === 1 ? 0xff : 0;
-      imageData.data[i * 4 + 2] = this.displayMemory[i] === 1 ? 0xff : 0;
-      imageData.data[i * 4 + 3] = 0xff;
-    }
-    this.ctx.putImageData(imageData, 0, 0);
-  }
-
-  clearDisplay() {
-    for (var i = 0; i < this.displayWidth * this.displayHeight; i++) {
-      this.displayMemory[i] = 0;
-    }
-  }
-
-  clearMemory() {
-    for (var i = 0x200; i < this.memorySize; i++) {
-      this.mainMemory[i] = 0;
-    }
-  }
-
-  clearRegisters() {
-    for (var i = 0; i < 16; i++) {
-      this.vRegisters[i] = 0;
-    }
-    this.chip8.clear_control_registers();
+    //only redraw the part of the display that changed since the last frame, if anything did
+    const region = this.chip8.take_dirty_region();
+    if (!region) return;
+
+    const rgbaPointer = this.chip8.render_display();
+    const width = this.chip8.get_display_rgba_width();
+    const height = this.chip8.get_display_rgba_height();
+    const scale = width / this.displayWidth;
+    const pixels = new Uint8ClampedArray(memory.buffer, rgbaPointer, width * height * 4);
+    this.ctx.putImageData(new ImageData(pixels, width, height), 0, 0, region.x * scale, region.y * scale, region.width * scale, region.height * scale);
+    region.free();
   }
 
   getOpcodeFromMemory(pc) {
@@ -102,52 +85,7 @@ class Emulator {
   }
 
   dissassembleOpcode(opcode) {
-    const x = (opcode & 0x0f00) >> 8;
-    const y = (opcode & 0x00f0) >> 4;
-    const nnn = opcode & 0x0fff;
-    const kk = opcode & 0x00ff;
-    const n = opcode & 0x000f;
-
-    if (opcode === 0x00e0) return "CLS";
-    if (opcode === 0x00ee) return "RET";
-    if (inRange(opcode, 0x1000, 0x1fff)) return `JP 0x${hex(nnn, 3)}`;
-    if (inRange(opcode, 0x2000, 0x2fff)) return `CALL 0x${hex(nnn, 3)}`;
-    if (inRange(opcode, 0x3000, 0x3fff)) return `SE V${n} ${hex(kk)}`;
-    if (inRange(opcode, 0x4000, 0x4fff)) return `SNE V${n} ${hex(kk)}`;
-    if (inRange(opcode, 0x5000, 0x5fff)) return `SE V${x} V${y}`;
-    if (inRange(opcode, 0x6000, 0x6fff)) return `LD V${x} ${hex(kk)}`;
-    if (inRange(opcode, 0x7000, 0x7fff)) return `ADD V${x} ${hex(kk)}`;
-    if (inRange(opcode, 0x8000, 0x8fff)) {
-      if (n === 0x0) return `LD V${x} V${y}`;
-      if (n === 0x1) return `OR V${x} V${y}`;
-      if (n === 0x2) return `AND V${x} V${y}`;
-      if (n === 0x3) return `XOR V${x} V${y}`;
-      if (n === 0x4) return `ADD V${x} V${y}`;
-      if (n === 0x5) return `SUB V${x} V${y}`;
-      if (n === 0x6) return `SHR V${x}`;
-      if (n === 0x7) return `SUBN V${x} V${y}`;
-      if (n === 0xe) return `SHL V${x}`;
-    }
-    if (inRange(opcode, 0x9000, 0x9fff)) return `SNE V${x} V${y}`;
-    if (inRange(opcode, 0xa000, 0xafff)) return `LDI ${hex(nnn, 3)}`;
-    if (inRange(opcode, 0xb000, 0xbfff)) return `JP V0 + ${hex(nnn, 3)}`;
-    if (inRange(opcode, 0xc000, 0xcfff)) return `RND ${hex(kk)}`;
-    if (inRange(opcode, 0xd000, 0xdfff)) return `DRW V${x} V${y} ${n}`;
-    if (inRange(opcode, 0xe000, 0xefff)) {
-      if (kk === 0x9e) return `SKP V${x}`;
-      if (kk === 0xa1) return `SKNP V${x}`;
-    }
-    if (inRange(opcode, 0xf000, 0xffff)) {
-      if (kk === 0x07) return `LD V${x} DT`;
-      if (kk === 0x0a) return `LD V${x} K`;
-      if (kk === 0x15) return `LD DT, V${x}`;
-      if (kk === 0x1e) return `ADD I, V${x}`;
-      if (kk === 0x29) return `LD F, V${x}`;
-      if (kk === 0x33) return `LD B, V${x}`;
-      if (kk === 0x55) return `LD [I], ${x}`;
-      if (kk === 0x65) return `LD ${x}, [I]`;
-    }
-    return "-";
+    return wasm.disassemble(opcode);
   }
 }
 
@@ -155,12 +93,27 @@ class Emulator {
 const writeProgramMemory = (emulator, length) => {
   var memory = "";
   const startpos = 0x200;
-  for (var i = 0; i <= length; i = i + 2) {
-    var opcode = emulator.getOpcodeFromMemory(startpos + i);
-    memory += `<li class="program-listing-line" id="mem_${hex(startpos + i, 4)}">[${hex(startpos + i, 4)}]: ${emulator.dissassembleOpcode(opcode)} (${hex(
-      opcode,
-      4
-    )})</li>`;
+  // Only bytes that can be reached by following the ROM's jumps and calls are shown as instructions,
+  // everything else is shown as data
+  const codeMap = emulator.chip8.get_code_map();
+  for (var i = 0; i < length; ) {
+    const label = emulator.chip8.get_label(startpos + i);
+    if (label) {
+      memory += `<li class="program-listing-label">${label}:</li>`;
+    }
+    if (codeMap[i]) {
+      var opcode = emulator.getOpcodeFromMemory(startpos + i);
+      memory += `<li class="program-listing-line" id="mem_${hex(startpos + i, 4)}">[${hex(startpos + i, 4)}]: ${emulator.dissassembleOpcode(opcode)} (${hex(
+        opcode,
+        4
+      )})</li>`;
+      i = i + 2;
+    } else {
+      memory += `<li class="program-listing-line program-listing-data" id="mem_${hex(startpos + i, 4)}">[${hex(startpos + i, 4)}]: DB ${hex(
+        emulator.mainMemory[startpos + i]
+      )}</li>`;
+      i = i + 1;
+    }
   }
 
   $("#memory #program-listing").html(memory);
@@ -195,9 +148,120 @@ const writeRegisters = (emulator) => {
   $("#memory #registers").html(registers);
 };
 
+const writeSprites = (emulator) => {
+  //drawing the whole ROM every frame is wasted work when nobody is looking at it
+  if (!$("#sprites").is(":visible")) return;
+
+  //the ROM is cut into strips as tall as the last sprite drawn, which lines most sprites up
+  const rows = emulator.chip8.get_last_sprite_length() || 8;
+  const sheet = emulator.chip8.render_sprites(0x200, emulator.romLength, wasm.SpriteFormat.Chip8, rows, 16, 3);
+  const width = sheet.get_width();
+  const height = sheet.get_height();
+  const pixels = new Uint8ClampedArray(sheet.get_rgba());
+  sheet.free();
+
+  const canvas = document.getElementById("sprite-sheet");
+  canvas.width = width;
+  canvas.height = height;
+  canvas.getContext("2d").putImageData(new ImageData(pixels, width, height), 0, 0);
+};
+
+/**** ROM DATABASE ****/
+//keys on the keyboard for the actions the ROM database knows about, on top of the hex keypad mapping
+const ACTION_KEYS = {
+  ArrowUp: "up",
+  ArrowDown: "down",
+  ArrowLeft: "left",
+  ArrowRight: "right",
+  " ": "a",
+  Shift: "b",
+};
+var romKeys = {};
+
+const applyRomSettings = (emulator) => {
+  romKeys = {};
+  for (const [key, action] of Object.entries(ACTION_KEYS)) {
+    const chip8Key = emulator.chip8.get_rom_key(action);
+    if (chip8Key !== undefined) romKeys[key] = chip8Key;
+  }
+
+  const title = emulator.chip8.get_rom_title();
+  const authors = emulator.chip8.get_rom_authors();
+  const actions = emulator.chip8.get_rom_actions();
+  var info = title ? `${title}${authors ? " by " + authors : ""}` : "";
+  if (actions) info += ` (arrow keys, space and shift play ${actions.split(" ").join(", ")})`;
+  $("#rom-info").text(info);
+};
+
+/**** CHEATS ****/
+const SEARCH_CANDIDATES_SHOWN = 50;
+
+//cheats are kept in local storage under the hash of the ROM they were found in
+const saveCheats = (emulator) => {
+  localStorage.setItem(`cheats_${emulator.chip8.get_rom_hash()}`, emulator.chip8.get_cheats());
+};
+
+const loadCheats = (emulator) => {
+  const cheats = localStorage.getItem(`cheats_${emulator.chip8.get_rom_hash()}`);
+  if (cheats) emulator.chip8.load_cheats(cheats);
+};
+
+const writeCheats = (emulator) => {
+  var pinned = "";
+  //skip the header and the line with the ROM's hash
+  emulator.chip8
+    .get_cheats()
+    .split("\n")
+    .slice(2)
+    .filter((line) => line)
+    .forEach((line) => {
+      pinned += `<li data-location="${line.split(" ")[0]}">${line}</li>`;
+    });
+  $("#pinned-cheats").html(pinned);
+};
+
+const writeSearchCandidates = (emulator, count) => {
+  var candidates = "";
+  emulator.chip8
+    .get_search_candidates(SEARCH_CANDIDATES_SHOWN)
+    .split("\n")
+    .filter((line) => line)
+    .forEach((line) => {
+      const [location, value] = line.split(" ");
+      candidates += `<li data-location="${location}" data-value="${value}">${location} = ${value}</li>`;
+    });
+  $("#search-count").text(`${count} candidate(s)`);
+  $("#search-candidates").html(candidates);
+};
+
+document.getElementById("search_start_button").onclick = function () {
+  em.chip8.start_memory_search();
+  writeSearchCandidates(em, em.chip8.get_memory_size() + 16);
+};
+
+$(".search-filter").on("click", (e) => {
+  writeSearchCandidates(em, em.chip8.filter_memory_search(wasm.SearchFilter[e.target.value]));
+});
+
+$("#search-candidates").on("click", "li", (e) => {
+  const name = prompt(`Name for the cheat pinning ${e.target.dataset.location} to ${e.target.dataset.value}`, "");
+  if (name === null) return;
+  em.chip8.pin_cheat(e.target.dataset.location, parseInt(e.target.dataset.value, 16), name);
+  saveCheats(em);
+  writeCheats(em);
+});
+
+$("#pinned-cheats").on("click", "li", (e) => {
+  em.chip8.unpin_cheat(e.target.dataset.location);
+  saveCheats(em);
+  writeCheats(em);
+});
+
 /**** BUTTONS ****/
 var is_step_through = false;
 var is_running = false;
+//the state the last frame ended in
+var run_state = wasm.RunState.Running;
 document.getElementById("step").onclick = function () {
   is_step_through = document.getElementById("step").checked;
   if (is_step_through) {
@@ -207,6 +271,15 @@ document.getElementById("step").onclick = function () {
   }
 };
 
+document.getElementById("protect").onclick = function () {
+  //the protection stays in place across resets and ROM changes until it's turned off
+  if (document.getElementById("protect").checked) {
+    em.chip8.protect_interpreter_area();
+  } else {
+    em.chip8.clear_memory_protection();
+  }
+};
+
 document.getElementById("go_button").onclick = function () {
   if (is_step_through) {
     renderLoop();
@@ -231,21 +304,82 @@ const resetEmulator = () => {
   is_running = false;
 
   document.getElementById("go_button").value = "Run";
-
-  em.clearDisplay();
-  em.clearRegisters();
-  em.clearMemory();
+  $("#memory-fault").text("");
+  $("#run-state").text("");
+  run_state = wasm.RunState.Running;
 };
 
-document.getElementById("reset_button").onclick = function () {
-  loadRom(document.getElementById("roms").value);
+// A cold reset refills memory and loads the ROM again, while a warm one restarts it with memory as it was
+const restartRom = (kind) => {
+  resetEmulator();
+  em.chip8.reset(kind);
+  writeProgramMemory(em, em.romLength);
   highlightCurrentOpcode(em);
   writeRegisters(em);
+  writeSprites(em);
   em.updateDisplay();
 };
 
+document.getElementById("reset_button").onclick = function () {
+  restartRom(wasm.ResetKind.Cold);
+};
+
+document.getElementById("restart_button").onclick = function () {
+  restartRom(wasm.ResetKind.Warm);
+};
+
+document.getElementById("ram-fill").addEventListener("change", (e) => {
+  em.chip8.set_ram_fill(wasm.FillPattern[e.target.value]);
+  restartRom(wasm.ResetKind.Cold);
+  e.target.blur();
+});
+
+document.getElementById("screenshot_button").onclick = function () {
+  const png = new Blob([em.chip8.take_screenshot()], { type: "image/png" });
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(png);
+  link.download = `${document.getElementById("roms").value}.png`;
+  link.click();
+  URL.revokeObjectURL(link.href);
+};
+
+document.getElementById("record_button").onclick = function () {
+  if (!em.chip8.is_recording_gif()) {
+    em.chip8.start_gif_recording(4, true);
+    document.getElementById("record_button").value = "Stop Recording";
+    return;
+  }
+
+  const gif = new Blob([em.chip8.stop_gif_recording()], { type: "image/gif" });
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(gif);
+  link.download = `${document.getElementById("roms").value}.gif`;
+  link.click();
+  URL.revokeObjectURL(link.href);
+  document.getElementById("record_button").value = "Record GIF";
+};
+
+document.getElementById("symbols_button").onclick = function () {
+  document.getElementById("symbols_file").click();
+};
+
+document.getElementById("symbols_file").addEventListener("change", (e) => {
+  const file = e.target.files[0];
+  if (!file) return;
+  file.text().then((text) => {
+    if (!em.chip8.load_symbols(text)) {
+      alert(`${file.name} is not a valid symbol file`);
+      return;
+    }
+    writeProgramMemory(em, em.romLength);
+    highlightCurrentOpcode(em);
+  });
+  e.target.value = "";
+});
+
 window.hideOutput = function (id) {
   $(`#${id}`).toggle();
+  if (id == "sprites") writeSprites(em);
   if ($(`#hide-button-${id}`).val().localeCompare("+") == 0) {
     $(`#hide-button-${id}`).val("-");
   } else {
@@ -258,18 +392,22 @@ const loadRom = (rom) => {
     .then((romData) => romData.arrayBuffer())
     .then((romDataArrayBuffer) => {
       resetEmulator();
-      const romDataView = new DataView(romDataArrayBuffer, 0, romDataArrayBuffer.byteLength);
-      for (var i = 0; i < romDataView.byteLength; i++) {
-        em.mainMemory[0x200 + i] = romDataView.getUint8(i);
-      }
-      writeProgramMemory(em, romDataView.byteLength);
+      em.chip8.load_rom(new Uint8Array(romDataArrayBuffer));
+      em.romLength = romDataArrayBuffer.byteLength;
+      applyRomSettings(em);
+      loadCheats(em);
+      writeCheats(em);
+      writeProgramMemory(em, em.romLength);
       highlightCurrentOpcode(em);
       writeRegisters(em);
+      writeSprites(em);
       em.updateDisplay();
     });
 };
 
 document.getElementById("roms").addEventListener("change", (e) => {
+  // Symbols belong to a particular ROM, so they are only kept when the same ROM is reset
+  em.chip8.clear_symbols();
   loadRom(e.target.value);
   document.getElementById("roms").blur();
 });
@@ -311,12 +449,17 @@ var keyMap = {
 document.addEventListener("keydown", function (event) {
   if (keyMap[event.key]) {
     em.chip8.press_key(keyMap[event.key]);
+  } else if (romKeys[event.key] !== undefined) {
+    em.chip8.press_key(romKeys[event.key]);
+    event.preventDefault();
   }
 });
 
 document.addEventListener("keyup", function (event) {
   if (keyMap[event.key]) {
     em.chip8.release_key(keyMap[event.key]);
+  } else if (romKeys[event.key] !== undefined) {
+    em.chip8.release_key(romKeys[event.key]);
   }
 });
 /******************/
@@ -342,15 +485,31 @@ function renderLoop() {
   } else if (is_running) {
     //otherwise, execute 8 cycles on every frame (We want to run the emulation at close to 500Hz,
     // which is the normal operating clockspeed of the chip8. Since requestAnimationFrame() runs at
-    // 60fps, executing 8 cycles per frame will give us a clockspeed of 480Hz).
-    for (var i = 0; i < 8; i++) {
-      em.chip8.execute_cycle();
-      if (!is_running) break;
+    // 60fps, executing 8 cycles per frame will give us a clockspeed of 480Hz), unless the ROM
+    // database knows the ROM needs a different speed.
+    // The rest of the frame is skipped once the ROM is idle, and a ROM that has halted is stopped
+    // rather than left spinning.
+    const cycles = em.chip8.get_rom_tickrate() || 8;
+    run_state = em.chip8.run_frame(cycles);
+    if (run_state == wasm.RunState.Halted) {
+      is_running = false;
+      document.getElementById("go_button").value = "Run";
+      $("#run-state").text(`Halted at ${hex(em.chip8.get_pc(), 4)}`);
     }
-    if (is_running) em.chip8.decrement_timers();
   }
 
-  highlightCurrentOpcode(em);
+  //a memory fault stops the emulator on the instruction that caused it, like a breakpoint
+  if (em.chip8.has_memory_fault()) {
+    is_running = false;
+    document.getElementById("go_button").value = "Run";
+    $("#memory-fault").text(em.chip8.get_memory_fault_text());
+  }
+
+  //while the ROM waits, only the registers and display can change, so the rest isn't redrawn
+  if (run_state != wasm.RunState.Idle) {
+    highlightCurrentOpcode(em);
+    writeSprites(em);
+  }
   writeRegisters(em);
   em.updateDisplay();
 